@@ -5,16 +5,26 @@ pub fn random_seed() -> u64 {
     RandomState::new().build_hasher().finish()
 }
 
-pub fn random_numbers() -> impl Iterator<Item=u64> {
-    let mut random = random_seed();
+pub fn xorshift(mut random: u64) -> u64 {
+    random ^= random << 13;
+    random ^= random >> 17;
+    random ^= random << 5;
+    random
+}
+
+pub fn seeded_random_numbers(seed: u64) -> impl Iterator<Item=u64> {
+    // Xorshift gets stuck on zero.
+    let mut random = if seed == 0 { u64::MAX } else { seed };
     std::iter::repeat_with(move || {
-        random ^= random << 13;
-        random ^= random >> 17;
-        random ^= random << 5;
+        random = xorshift(random);
         random
     })
 }
 
+pub fn random_numbers() -> impl Iterator<Item=u64> {
+    seeded_random_numbers(random_seed())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -24,4 +34,11 @@ mod tests {
         let result = random_numbers().next();
         assert_ne!(result, None)
     }
+
+    #[test]
+    fn seeded_random_numbers_works() {
+        let first: Vec<u64> = seeded_random_numbers(42).take(10).collect();
+        let second: Vec<u64> = seeded_random_numbers(42).take(10).collect();
+        assert_eq!(first, second)
+    }
 }
@@ -1,6 +1,13 @@
-pub trait Activation {
-    fn activate(&self, input: f32) -> f32;
-    fn derivative(&self, input: f32) -> f32;
+#[cfg(test)]
+use crate::autodiff::Var;
+use crate::float::Float;
+
+pub trait Activation<T: Float> {
+    fn activate(&self, input: T) -> T;
+    fn derivative(&self, input: T) -> T;
+    // The activation function on an autodiff tape.
+    #[cfg(test)]
+    fn expression<'t>(&self, input: Var<'t, T>) -> Var<'t, T>;
 }
 
 struct Sigmoid;
@@ -11,55 +18,78 @@ struct ReLU;
 
 struct SiLU;
 
-impl Activation for Sigmoid {
-    fn activate(&self, input: f32) -> f32 {
-        1.0 / (1.0 + (-input).exp())
+impl<T: Float> Activation<T> for Sigmoid {
+    fn activate(&self, input: T) -> T {
+        T::one() / (T::one() + (-input).exp())
     }
 
-    fn derivative(&self, input: f32) -> f32 {
+    fn derivative(&self, input: T) -> T {
         let activate = self.activate(input);
 
-        activate * (1.0 - activate)
+        activate * (T::one() - activate)
+    }
+
+    #[cfg(test)]
+    fn expression<'t>(&self, input: Var<'t, T>) -> Var<'t, T> {
+        ((-input).exp() + T::one()).recip()
     }
 }
 
-impl Activation for TanH {
-    fn activate(&self, input: f32) -> f32 {
-        let e2 = (2.0 * input).exp();
+impl<T: Float> Activation<T> for TanH {
+    fn activate(&self, input: T) -> T {
+        let e2 = (T::of(2.0) * input).exp();
 
-        (e2 - 1.0) / (e2 + 1.0)
+        (e2 - T::one()) / (e2 + T::one())
     }
 
-    fn derivative(&self, input: f32) -> f32 {
+    fn derivative(&self, input: T) -> T {
         let t = self.activate(input);
 
-        1.0 - t * t
+        T::one() - t * t
+    }
+
+    #[cfg(test)]
+    fn expression<'t>(&self, input: Var<'t, T>) -> Var<'t, T> {
+        let e2 = (input * T::of(2.0)).exp();
+
+        (e2 - T::one()) / (e2 + T::one())
     }
 }
 
-impl Activation for ReLU {
-    fn activate(&self, input: f32) -> f32 {
-        input.max(0.0)
+impl<T: Float> Activation<T> for ReLU {
+    fn activate(&self, input: T) -> T {
+        input.max(T::zero())
     }
 
-    fn derivative(&self, input: f32) -> f32 {
-        if input > 0.0 { 1.0 } else { 0.0 }
+    fn derivative(&self, input: T) -> T {
+        if input > T::zero() { T::one() } else { T::zero() }
+    }
+
+    #[cfg(test)]
+    fn expression<'t>(&self, input: Var<'t, T>) -> Var<'t, T> {
+        input.max(T::zero())
     }
 }
 
-impl Activation for SiLU {
-    fn activate(&self, input: f32) -> f32 {
-        input / (1.0 + (-input).exp())
+impl<T: Float> Activation<T> for SiLU {
+    fn activate(&self, input: T) -> T {
+        input / (T::one() + (-input).exp())
     }
 
-    fn derivative(&self, input: f32) -> f32 {
-        let sig = 1.0 / (1.0 + (-input).exp());
+    fn derivative(&self, input: T) -> T {
+        let sig = T::one() / (T::one() + (-input).exp());
 
-        input * sig * (1.0 - sig) + sig
+        input * sig * (T::one() - sig) + sig
+    }
+
+    #[cfg(test)]
+    fn expression<'t>(&self, input: Var<'t, T>) -> Var<'t, T> {
+        input / ((-input).exp() + T::one())
     }
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ActivationType {
     SIGMOID,
     TANH,
@@ -67,10 +97,39 @@ pub enum ActivationType {
     SILU,
 }
 
+impl ActivationType {
+    pub const ALL: [ActivationType; 4] = [ActivationType::SIGMOID, ActivationType::TANH, ActivationType::RELU, ActivationType::SILU];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActivationType::SIGMOID => "sigmoid",
+            ActivationType::TANH => "tanh",
+            ActivationType::RELU => "relu",
+            ActivationType::SILU => "silu",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sigmoid" => Some(ActivationType::SIGMOID),
+            "tanh" => Some(ActivationType::TANH),
+            "relu" => Some(ActivationType::RELU),
+            "silu" => Some(ActivationType::SILU),
+            _ => None,
+        }
+    }
+
+    // The following type, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|activation_type| activation_type == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
 pub struct Activations;
 
 impl Activations {
-    pub fn get_activation(activation_type: &ActivationType) -> Box<dyn Activation> {
+    pub fn get_activation<T: Float>(activation_type: &ActivationType) -> Box<dyn Activation<T>> {
         match activation_type {
             ActivationType::SIGMOID => Box::new(Sigmoid {}),
             ActivationType::TANH => Box::new(TanH {}),
@@ -0,0 +1,358 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::float::Float;
+use crate::layer::{Layer, Parameters};
+
+// Reverse-mode automatic differentiation: every operation on a `Var` is recorded on the tape,
+// which is then walked backwards to get the gradients of a result with respect to all variables.
+pub struct Tape<T: Float = f32> {
+    nodes: RefCell<Vec<Node<T>>>,
+}
+
+// Partial derivatives of a recorded value with respect to the (at most two) values it was calculated from.
+// Unused slots have a zero partial.
+struct Node<T: Float> {
+    partials: [(usize, T); 2],
+}
+
+#[derive(Clone, Copy)]
+pub struct Var<'t, T: Float = f32> {
+    tape: &'t Tape<T>,
+    index: usize,
+    pub value: T,
+}
+
+// Gradients of a result with respect to every value on the tape.
+pub struct Gradients<T: Float> {
+    adjoints: Vec<T>,
+}
+
+impl<T: Float> Tape<T> {
+    pub fn new() -> Self {
+        Tape { nodes: RefCell::new(vec![]) }
+    }
+
+    // Create a variable (or constant) to build expressions with.
+    pub fn var(&self, value: T) -> Var<'_, T> {
+        let index = self.push([(0, T::zero()), (0, T::zero())]);
+
+        Var { tape: self, index, value }
+    }
+
+    pub fn vars(&self, values: &[T]) -> Vec<Var<'_, T>> {
+        values.iter().map(|&value| self.var(value)).collect()
+    }
+
+    fn push(&self, partials: [(usize, T); 2]) -> usize {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(Node { partials });
+
+        nodes.len() - 1
+    }
+}
+
+impl<T: Float> Default for Tape<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'t, T: Float> Var<'t, T> {
+    fn unary(self, value: T, partial: T) -> Self {
+        let index = self.tape.push([(self.index, partial), (self.index, T::zero())]);
+
+        Var { tape: self.tape, index, value }
+    }
+
+    fn binary(self, other: Self, value: T, partial: T, other_partial: T) -> Self {
+        let index = self.tape.push([(self.index, partial), (other.index, other_partial)]);
+
+        Var { tape: self.tape, index, value }
+    }
+
+    pub fn exp(self) -> Self {
+        let value = self.value.exp();
+
+        self.unary(value, value)
+    }
+
+    pub fn ln(self) -> Self {
+        self.unary(self.value.ln(), T::one() / self.value)
+    }
+
+    pub fn sqrt(self) -> Self {
+        let value = self.value.sqrt();
+
+        self.unary(value, T::one() / (T::of(2.0) * value))
+    }
+
+    pub fn powi(self, n: i32) -> Self {
+        self.unary(self.value.powi(n), T::of(n as f64) * self.value.powi(n - 1))
+    }
+
+    pub fn recip(self) -> Self {
+        self.unary(self.value.recip(), -T::one() / (self.value * self.value))
+    }
+
+    pub fn abs(self) -> Self {
+        self.unary(self.value.abs(), self.value.signum())
+    }
+
+    // Maximum of the variable and a constant, e.g. `x.max(0)` for ReLU. The gradient goes to the variable only if it is larger.
+    pub fn max(self, other: T) -> Self {
+        if self.value > other {
+            self.unary(self.value, T::one())
+        } else {
+            self.unary(other, T::zero())
+        }
+    }
+
+    pub fn sum<I: IntoIterator<Item = Self>>(tape: &'t Tape<T>, vars: I) -> Self {
+        vars.into_iter().fold(tape.var(T::zero()), |sum, var| sum + var)
+    }
+
+    // Walk the tape backwards to get the gradients of this value with respect to everything it was calculated from.
+    pub fn gradients(&self) -> Gradients<T> {
+        let nodes = self.tape.nodes.borrow();
+        let mut adjoints = vec![T::zero(); self.index + 1];
+        adjoints[self.index] = T::one();
+
+        for index in (0..=self.index).rev() {
+            let adjoint = adjoints[index];
+            if adjoint == T::zero() {
+                continue;
+            }
+
+            for &(parent, partial) in &nodes[index].partials {
+                adjoints[parent] += adjoint * partial;
+            }
+        }
+
+        Gradients { adjoints }
+    }
+}
+
+impl<T: Float> Gradients<T> {
+    // Derivative of the result with respect to the variable.
+    pub fn wrt(&self, var: &Var<'_, T>) -> T {
+        self.adjoints.get(var.index).copied().unwrap_or_else(T::zero)
+    }
+}
+
+impl<'t, T: Float> Add for Var<'t, T> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.binary(other, self.value + other.value, T::one(), T::one())
+    }
+}
+
+impl<'t, T: Float> Sub for Var<'t, T> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.binary(other, self.value - other.value, T::one(), -T::one())
+    }
+}
+
+impl<'t, T: Float> Mul for Var<'t, T> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.binary(other, self.value * other.value, other.value, self.value)
+    }
+}
+
+impl<'t, T: Float> Div for Var<'t, T> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let value = self.value / other.value;
+
+        self.binary(other, value, T::one() / other.value, -value / other.value)
+    }
+}
+
+impl<'t, T: Float> Neg for Var<'t, T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.unary(-self.value, -T::one())
+    }
+}
+
+impl<'t, T: Float> Add<T> for Var<'t, T> {
+    type Output = Self;
+
+    fn add(self, other: T) -> Self {
+        self.unary(self.value + other, T::one())
+    }
+}
+
+impl<'t, T: Float> Sub<T> for Var<'t, T> {
+    type Output = Self;
+
+    fn sub(self, other: T) -> Self {
+        self.unary(self.value - other, T::one())
+    }
+}
+
+impl<'t, T: Float> Mul<T> for Var<'t, T> {
+    type Output = Self;
+
+    fn mul(self, other: T) -> Self {
+        self.unary(self.value * other, other)
+    }
+}
+
+impl<'t, T: Float> Div<T> for Var<'t, T> {
+    type Output = Self;
+
+    fn div(self, other: T) -> Self {
+        self.unary(self.value / other, T::one() / other)
+    }
+}
+
+// Builds the outputs of a single data point from the parameter groups and the inputs.
+pub type Expression<T> = Box<dyn for<'t> Fn(&[Vec<Var<'t, T>>], &[Var<'t, T>]) -> Vec<Var<'t, T>>>;
+
+// Layer defined only by its forward expression, the backward pass comes from the tape.
+// It can not be saved, since the expression is code.
+pub struct ExpressionLayer<T: Float = f32> {
+    pub output_shape: Vec<usize>,
+    pub parameters: Vec<Vec<T>>,
+    pub cost_gradients: Vec<Vec<T>>,
+    expression: Expression<T>,
+    // Inputs of the last batch.
+    inputs: Vec<Vec<T>>,
+}
+
+impl<T: Float> ExpressionLayer<T> {
+    pub fn new(output_shape: Vec<usize>, parameters: Vec<Vec<T>>, expression: Expression<T>) -> Self {
+        let cost_gradients = parameters.iter().map(|values| vec![T::zero(); values.len()]).collect();
+
+        ExpressionLayer {
+            output_shape,
+            parameters,
+            cost_gradients,
+            expression,
+            inputs: vec![],
+        }
+    }
+}
+
+impl<T: Float> Layer<T> for ExpressionLayer<T> {
+    fn name(&self) -> &'static str {
+        "expression"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        self.output_shape.clone()
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        let outputs = inputs.iter()
+            .map(|input| {
+                let tape = Tape::new();
+                let parameters: Vec<Vec<Var<T>>> = self.parameters.iter().map(|values| tape.vars(values)).collect();
+
+                (self.expression)(&parameters, &tape.vars(input)).iter().map(|output| output.value).collect()
+            })
+            .collect();
+
+        self.inputs = inputs;
+
+        outputs
+    }
+
+    // The expression is recorded again for every data point, with the output gradients as weights of the outputs.
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        for gradients in &mut self.cost_gradients {
+            gradients.fill(T::zero());
+        }
+
+        self.inputs.iter()
+            .zip(&output_gradients)
+            .map(|(input, output_gradients)| {
+                let tape = Tape::new();
+                let parameters: Vec<Vec<Var<T>>> = self.parameters.iter().map(|values| tape.vars(values)).collect();
+                let inputs = tape.vars(input);
+
+                let outputs = (self.expression)(&parameters, &inputs);
+                let weighted = Var::sum(&tape, outputs.into_iter().zip(output_gradients).map(|(output, &gradient)| output * gradient));
+                let gradients = weighted.gradients();
+
+                for (cost_gradients, parameters) in self.cost_gradients.iter_mut().zip(&parameters) {
+                    for (cost_gradient, parameter) in cost_gradients.iter_mut().zip(parameters) {
+                        *cost_gradient += gradients.wrt(parameter);
+                    }
+                }
+
+                inputs.iter().map(|input| gradients.wrt(input)).collect()
+            })
+            .collect()
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        self.parameters.iter_mut()
+            .zip(self.cost_gradients.iter_mut())
+            .map(|(values, gradients)| Parameters { values, gradients, regularization: None })
+            .collect()
+    }
+
+    fn save(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "expression layers can not be saved"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::{Activations, ActivationType};
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        // Softmax cross entropy of the first value.
+        fn cost<'t>(tape: &'t Tape<f64>, values: &[Var<'t, f64>]) -> Var<'t, f64> {
+            let exps: Vec<Var<f64>> = values.iter().map(|value| value.exp()).collect();
+            let sum = Var::sum(tape, exps.iter().copied());
+
+            -(exps[0] / sum).ln() + (values[1] * values[2]).powi(2).sqrt() - values[2].abs().max(0.5) / 3.0
+        }
+
+        let values = [0.3, -1.2, 0.7];
+        let tape = Tape::new();
+        let vars = tape.vars(&values);
+        let gradients = cost(&tape, &vars).gradients();
+
+        let h = 1e-6;
+        for (index, var) in vars.iter().enumerate() {
+            let mut shifted = values;
+            shifted[index] += h;
+            let shifted_tape = Tape::new();
+            let up = cost(&shifted_tape, &shifted_tape.vars(&shifted)).value;
+            shifted[index] -= 2.0 * h;
+            let down = cost(&shifted_tape, &shifted_tape.vars(&shifted)).value;
+
+            assert!((gradients.wrt(var) - (up - down) / (2.0 * h)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn activation_expressions_match_derivatives() {
+        for activation_type in [ActivationType::SIGMOID, ActivationType::TANH, ActivationType::RELU, ActivationType::SILU] {
+            let activation = Activations::get_activation::<f64>(&activation_type);
+
+            for input in [-2.5, -0.3, 0.4, 1.7] {
+                let tape = Tape::new();
+                let var = tape.var(input);
+                let output = activation.expression(var);
+
+                assert!((output.value - activation.activate(input)).abs() < 1e-12);
+                assert!((output.gradients().wrt(&var) - activation.derivative(input)).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::float::Float;
+use crate::neural_network::{Divergence, NeuralNetwork};
+
+// Whether a callback lets the training go on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+// Counters and metrics of the training so far, passed to every callback.
+#[derive(Clone, Default, Debug)]
+pub struct TrainingState<T: Float = f32> {
+    // Number of the current epoch, starting at 1.
+    pub epoch: usize,
+    // Number of the current batch in the epoch, starting at 1.
+    pub batch: usize,
+    // Number of learn steps over all epochs.
+    pub step: usize,
+    pub learn_rate: Option<T>,
+    // Cost of the last batch before its update, the average data cost plus the regularization penalty.
+    pub batch_cost: Option<T>,
+    // Global norm of the cost gradients of the last batch.
+    pub gradient_norm: Option<T>,
+    // Average of the batch costs of the epoch and the accuracy on the training data, set at the end of the epoch.
+    pub training_cost: Option<T>,
+    pub training_accuracy: Option<T>,
+    // Metrics on the validation data, set at the end of the epoch if there is validation data.
+    pub validation_cost: Option<T>,
+    pub validation_accuracy: Option<T>,
+}
+
+// Hooks into the training loop, e.g. for logging, plots or stopping. Everything defaults to doing nothing.
+pub trait Callback<T: Float> {
+    fn on_train_start(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        Control::Continue
+    }
+
+    fn on_train_end(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) {}
+
+    fn on_epoch_start(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        Control::Continue
+    }
+
+    fn on_batch_end(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        Control::Continue
+    }
+
+    // The last learn step diverged and has been undone. Training stops afterwards.
+    fn on_divergence(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>, _divergence: &Divergence) {}
+}
+
+// Run a hook on every callback. Training stops if any of them asks for it, but all of them are called.
+pub fn notify<T, F>(callbacks: &mut [&mut dyn Callback<T>], mut hook: F) -> Control
+where
+    T: Float,
+    F: FnMut(&mut dyn Callback<T>) -> Control,
+{
+    callbacks.iter_mut().fold(Control::Continue, |control, callback| match hook(&mut **callback) {
+        Control::Stop => Control::Stop,
+        Control::Continue => control,
+    })
+}
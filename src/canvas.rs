@@ -0,0 +1,106 @@
+use crate::decision_boundary::BoundaryImage;
+
+// RGBA image drawn to on the CPU, e.g. to render without a window. Coordinates are in pixels from the top left corner.
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    // Rows from top to bottom.
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn from_image(image: BoundaryImage) -> Self {
+        Canvas {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels,
+        }
+    }
+
+    // Blend a color over a pixel, pixels outside the canvas are ignored.
+    pub fn blend(&mut self, x: i64, y: i64, color: [u8; 4]) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+
+        let index = (y as usize * self.width + x as usize) * 4;
+        let alpha = color[3] as f32 / 255.0;
+        for (pixel, &channel) in self.pixels[index..index + 3].iter_mut().zip(&color[..3]) {
+            *pixel = (*pixel as f32 * (1.0 - alpha) + channel as f32 * alpha).round() as u8;
+        }
+    }
+
+    pub fn fill_rect(&mut self, left: f32, top: f32, right: f32, bottom: f32, color: [u8; 4]) {
+        for y in top.round() as i64..bottom.round() as i64 {
+            for x in left.round() as i64..right.round() as i64 {
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32, color: [u8; 4]) {
+        self.fill_where(x - radius, y - radius, x + radius, y + radius, color, |px, py| {
+            (px - x).powi(2) + (py - y).powi(2) <= radius * radius
+        });
+    }
+
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: [u8; 4]) {
+        let half = width / 2.0;
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length_squared = (dx * dx + dy * dy).max(f32::EPSILON);
+
+        self.fill_where(from.0.min(to.0) - half, from.1.min(to.1) - half, from.0.max(to.0) + half, from.1.max(to.1) + half, color, |px, py| {
+            // Distance to the closest point of the segment.
+            let t = (((px - from.0) * dx + (py - from.1) * dy) / length_squared).clamp(0.0, 1.0);
+            (px - from.0 - t * dx).powi(2) + (py - from.1 - t * dy).powi(2) <= half * half
+        });
+    }
+
+    pub fn polyline(&mut self, points: &[(f32, f32)], width: f32, color: [u8; 4]) {
+        for segment in points.windows(2) {
+            self.line(segment[0], segment[1], width, color);
+        }
+    }
+
+    pub fn image(self) -> BoundaryImage {
+        BoundaryImage {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels,
+        }
+    }
+
+    // Blend the color over the pixels in a box whose centers are inside a shape.
+    fn fill_where<F: Fn(f32, f32) -> bool>(&mut self, left: f32, top: f32, right: f32, bottom: f32, color: [u8; 4], inside: F) {
+        for y in top.floor() as i64..=bottom.ceil() as i64 {
+            for x in left.floor() as i64..=right.ceil() as i64 {
+                if inside(x as f32 + 0.5, y as f32 + 0.5) {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_shapes_inside_the_canvas() {
+        let mut canvas = Canvas::from_image(BoundaryImage { width: 10, height: 10, pixels: [0, 0, 0, 255].repeat(100) });
+        let pixel = |canvas: &Canvas, x: usize, y: usize| canvas.pixels[(y * 10 + x) * 4..(y * 10 + x) * 4 + 4].to_vec();
+
+        canvas.fill_rect(-5.0, -5.0, 2.0, 2.0, [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 1, 1), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 2, 1), vec![0, 0, 0, 255]);
+
+        canvas.fill_circle(5.0, 5.0, 2.0, [0, 0, 255, 128]);
+        assert_eq!(pixel(&canvas, 5, 5), vec![0, 0, 128, 255]);
+        assert_eq!(pixel(&canvas, 8, 8), vec![0, 0, 0, 255]);
+
+        canvas.line((0.0, 9.5), (10.0, 9.5), 1.0, [0, 255, 0, 255]);
+        assert!((0..10).all(|x| pixel(&canvas, x, 9) == vec![0, 255, 0, 255]));
+        assert_eq!(pixel(&canvas, 5, 8), vec![0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,119 @@
+// Line segment between two points.
+pub type Segment = ((f32, f32), (f32, f32));
+
+// Values of a function on a grid over a rectangle, rows from bottom to top.
+pub struct Grid {
+    pub values: Vec<Vec<f32>>,
+    pub x_min: f32,
+    pub x_max: f32,
+    pub y_min: f32,
+    pub y_max: f32,
+}
+
+impl Grid {
+    // Sample a function at the corners of columns x rows cells.
+    pub fn sample<F: Fn(f32, f32) -> f32>(function: F, bounds: (f32, f32, f32, f32), columns: usize, rows: usize) -> Self {
+        let (x_min, x_max, y_min, y_max) = bounds;
+        let values = (0..=rows)
+            .map(|row| {
+                let y = y_min + (y_max - y_min) * row as f32 / rows as f32;
+                (0..=columns).map(|column| function(x_min + (x_max - x_min) * column as f32 / columns as f32, y)).collect()
+            })
+            .collect();
+
+        Grid { values, x_min, x_max, y_min, y_max }
+    }
+
+    // Smallest and largest finite value.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.values.iter()
+            .flatten()
+            .filter(|value| value.is_finite())
+            .fold(None, |range, &value| match range {
+                None => Some((value, value)),
+                Some((min, max)) => Some((min.min(value), max.max(value))),
+            })
+    }
+
+    // Contour lines where the function crosses a level (marching squares), in the coordinates of the function.
+    pub fn contour(&self, level: f32) -> Vec<Segment> {
+        let rows = self.values.len() - 1;
+        let columns = self.values[0].len() - 1;
+        let to_bounds = |(column, row): (f32, f32)| (
+            self.x_min + (self.x_max - self.x_min) * column / columns as f32,
+            self.y_min + (self.y_max - self.y_min) * row / rows as f32,
+        );
+
+        let mut segments = vec![];
+        for row in 0..rows {
+            for column in 0..columns {
+                let corners = [
+                    ((column, row), self.values[row][column]),
+                    ((column + 1, row), self.values[row][column + 1]),
+                    ((column + 1, row + 1), self.values[row + 1][column + 1]),
+                    ((column, row + 1), self.values[row + 1][column]),
+                ];
+                if corners.iter().any(|(_, value)| !value.is_finite()) {
+                    continue;
+                }
+
+                // Crossings on the bottom, right, top and left edges.
+                let crossings: Vec<(f32, f32)> = (0..4)
+                    .filter_map(|edge| {
+                        let ((x0, y0), a) = corners[edge];
+                        let ((x1, y1), b) = corners[(edge + 1) % 4];
+                        if (a >= level) == (b >= level) {
+                            return None;
+                        }
+
+                        let t = (level - a) / (b - a);
+                        Some((x0 as f32 + t * (x1 as f32 - x0 as f32), y0 as f32 + t * (y1 as f32 - y0 as f32)))
+                    })
+                    .collect();
+
+                match crossings.len() {
+                    2 => segments.push((to_bounds(crossings[0]), to_bounds(crossings[1]))),
+                    4 => {
+                        // Saddle: the center decides which corners are connected.
+                        let center = corners.iter().map(|(_, value)| value).sum::<f32>() / 4.0;
+                        let pairs = if (center >= level) == (corners[0].1 >= level) { [(0, 1), (2, 3)] } else { [(0, 3), (1, 2)] };
+                        for (a, b) in pairs {
+                            segments.push((to_bounds(crossings[a]), to_bounds(crossings[b])));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        segments
+    }
+}
+
+// Levels between a minimum and a maximum, closer together near the minimum where minima of functions are.
+pub fn levels(min: f32, max: f32, count: usize) -> Vec<f32> {
+    (1..=count)
+        .map(|level| min + (max - min) * (level as f32 / (count + 1) as f32).powi(2))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contours_of_a_bowl_are_circles() {
+        let grid = Grid::sample(|x, y| x * x + y * y, (-2.0, 2.0, -2.0, 2.0), 40, 40);
+        assert_eq!(grid.range(), Some((0.0, 8.0)));
+
+        let segments = grid.contour(1.0);
+        assert!(segments.len() > 20);
+        for ((x0, y0), (x1, y1)) in segments {
+            assert!(((x0 * x0 + y0 * y0).sqrt() - 1.0).abs() < 0.01);
+            assert!(((x1 * x1 + y1 * y1).sqrt() - 1.0).abs() < 0.01);
+        }
+
+        assert!(grid.contour(9.0).is_empty());
+        assert_eq!(levels(0.0, 16.0, 3), vec![1.0, 4.0, 9.0]);
+    }
+}
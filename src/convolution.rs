@@ -0,0 +1,527 @@
+use std::io::{self, BufRead, Write};
+
+use crate::activation::{Activation, Activations, ActivationType};
+use crate::float::Float;
+use crate::layer::{self, Layer, Parameters};
+use crate::regularization::Regularization;
+use crate::serialization::{self, RecordReader};
+
+// Images are flattened channel by channel, row by row: index = (channel * height + y) * width + x.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ImageShape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl ImageShape {
+    pub fn from_shape(shape: &[usize]) -> Self {
+        assert_eq!(shape.len(), 3, "expected an image shape [channels, height, width], got {:?}", shape);
+
+        ImageShape {
+            channels: shape[0],
+            height: shape[1],
+            width: shape[2],
+        }
+    }
+
+    pub fn to_shape(self) -> Vec<usize> {
+        vec![self.channels, self.height, self.width]
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    fn index(&self, channel: usize, y: usize, x: usize) -> usize {
+        (channel * self.height + y) * self.width + x
+    }
+
+    // Shape of the outputs of a square window sliding over the padded image, or why it can not slide.
+    fn slide(&self, channels: usize, window: usize, stride: usize, padding: usize) -> Result<ImageShape, String> {
+        if channels == 0 || window == 0 || stride == 0 {
+            return Err(format!("channels ({}), window size ({}) and stride ({}) must not be 0", channels, window, stride));
+        }
+
+        let positions = |size: usize| (size + 2 * padding).checked_sub(window)
+            .map(|room| room / stride + 1)
+            .ok_or_else(|| format!("a window of size {} does not fit into {}x{} images with padding {}", window, self.height, self.width, padding));
+
+        Ok(ImageShape {
+            channels,
+            height: positions(self.height)?,
+            width: positions(self.width)?,
+        })
+    }
+}
+
+// 2D convolution with square kernels, followed by an activation function.
+pub struct Conv2D<T: Float = f32> {
+    pub input_shape: ImageShape,
+    pub output_shape: ImageShape,
+    pub kernel_size: usize,
+    pub stride: usize,
+    pub padding: usize,
+    // One row per filter: channels * kernel_size * kernel_size weights.
+    pub weights: Vec<Vec<T>>,
+    pub biases: Vec<T>,
+    pub cost_gradient_weights: Vec<Vec<T>>,
+    pub cost_gradient_biases: Vec<T>,
+    pub activation_type: ActivationType,
+    pub activation: Box<dyn Activation<T>>,
+    pub regularization: Regularization<T>,
+    // Learn data of the last batch.
+    inputs: Vec<Vec<T>>,
+    weighted_inputs: Vec<Vec<T>>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PoolType {
+    MAX,
+    AVERAGE,
+}
+
+// Downsamples every channel by taking the maximum or average of each window.
+pub struct Pool2D {
+    pub pool_type: PoolType,
+    pub input_shape: ImageShape,
+    pub output_shape: ImageShape,
+    pub size: usize,
+    pub stride: usize,
+    // Input index of the maximum of every output, for the backward pass of max pooling.
+    max_indices: Vec<Vec<usize>>,
+}
+
+// Turns an image into a plain vector. The values are already stored flat, so only the shape changes.
+pub struct Flatten;
+
+impl<T: Float> Conv2D<T> {
+    pub fn new(input_shape: &[usize], filters: usize, kernel_size: usize, stride: usize, padding: usize, activation_type: &ActivationType) -> Self {
+        Self::seeded(input_shape, filters, kernel_size, stride, padding, activation_type, snippets::random_seed())
+    }
+
+    // Create the layer with the random weights of a seed, so it starts the same every time.
+    pub fn seeded(
+        input_shape: &[usize],
+        filters: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: usize,
+        activation_type: &ActivationType,
+        seed: u64,
+    ) -> Self {
+        let input_shape = ImageShape::from_shape(input_shape);
+        let output_shape = input_shape.slide(filters, kernel_size, stride, padding)
+            .unwrap_or_else(|message| panic!("invalid conv2d: {}", message));
+
+        let kernel_len = input_shape.channels * kernel_size * kernel_size;
+        // Scale the initial weights by the number of inputs of every output, so deep stacks do not saturate.
+        let boundary = T::one() / T::of(kernel_len as f64).sqrt();
+        let mut random = snippets::seeded_random_numbers(seed);
+        let weights = (0..filters)
+            .map(|_| (0..kernel_len).map(|_| -boundary + T::unit(random.next().unwrap()) * T::of(2.0) * boundary).collect())
+            .collect();
+
+        Conv2D {
+            input_shape,
+            output_shape,
+            kernel_size,
+            stride,
+            padding,
+            weights,
+            biases: vec![T::zero(); filters],
+            cost_gradient_weights: vec![vec![T::zero(); kernel_len]; filters],
+            cost_gradient_biases: vec![T::zero(); filters],
+            activation_type: *activation_type,
+            activation: Activations::get_activation(activation_type),
+            regularization: Regularization::none(),
+            inputs: vec![],
+            weighted_inputs: vec![],
+        }
+    }
+
+    // Call the function for every (weight index, input index, output index) of the convolution,
+    // skipping the parts of the kernels lying in the padding.
+    fn for_each_connection<F: FnMut(usize, usize, usize)>(&self, filter: usize, mut function: F) {
+        let input = self.input_shape;
+        let output = self.output_shape;
+
+        for out_y in 0..output.height {
+            for out_x in 0..output.width {
+                let output_index = output.index(filter, out_y, out_x);
+
+                for channel in 0..input.channels {
+                    for kernel_y in 0..self.kernel_size {
+                        let y = (out_y * self.stride + kernel_y) as isize - self.padding as isize;
+                        if y < 0 || y >= input.height as isize {
+                            continue;
+                        }
+
+                        for kernel_x in 0..self.kernel_size {
+                            let x = (out_x * self.stride + kernel_x) as isize - self.padding as isize;
+                            if x < 0 || x >= input.width as isize {
+                                continue;
+                            }
+
+                            let weight_index = (channel * self.kernel_size + kernel_y) * self.kernel_size + kernel_x;
+                            function(weight_index, input.index(channel, y as usize, x as usize), output_index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let record = reader.record("conv2d")?;
+        let value = |index: usize| serialization::parse::<usize>(record.get(index), "conv2d");
+        let activation_type = record.get(7)
+            .and_then(|name| ActivationType::from_name(name))
+            .ok_or_else(|| serialization::invalid_data("unknown activation type".to_string()))?;
+
+        let input_shape = [value(0)?, value(1)?, value(2)?];
+        let (filters, kernel_size, stride, padding) = (value(3)?, value(4)?, value(5)?, value(6)?);
+        ImageShape::from_shape(&input_shape).slide(filters, kernel_size, stride, padding)
+            .map_err(|message| serialization::invalid_data(format!("invalid conv2d: {}", message)))?;
+
+        let mut conv = Conv2D::new(&input_shape, filters, kernel_size, stride, padding, &activation_type);
+        let kernel_len = input_shape[0] * kernel_size * kernel_size;
+        for filter in 0..conv.output_shape.channels {
+            conv.weights[filter] = layer::read_exact(reader, "weights", kernel_len)?;
+        }
+        conv.biases = layer::read_exact(reader, "biases", conv.output_shape.channels)?;
+
+        Ok(conv)
+    }
+}
+
+impl<T: Float> Layer<T> for Conv2D<T> {
+    fn name(&self) -> &'static str {
+        "conv2d"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        self.output_shape.to_shape()
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        let mut weighted_inputs = Vec::with_capacity(inputs.len());
+
+        for input in &inputs {
+            let mut weighted_input = vec![T::zero(); self.output_shape.len()];
+
+            for filter in 0..self.output_shape.channels {
+                let weights = &self.weights[filter];
+                self.for_each_connection(filter, |weight_index, input_index, output_index| {
+                    weighted_input[output_index] += weights[weight_index] * input[input_index];
+                });
+            }
+
+            for (index, value) in weighted_input.iter_mut().enumerate() {
+                *value += self.biases[index / (self.output_shape.height * self.output_shape.width)];
+            }
+
+            weighted_inputs.push(weighted_input);
+        }
+
+        // Apply activation function.
+        let outputs = weighted_inputs.iter()
+            .map(|weighted_input: &Vec<T>| weighted_input.iter().map(|&value| self.activation.activate(value)).collect())
+            .collect();
+
+        self.inputs = inputs;
+        self.weighted_inputs = weighted_inputs;
+
+        outputs
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let mut node_values = output_gradients;
+
+        for (values, weighted_input) in node_values.iter_mut().zip(&self.weighted_inputs) {
+            for (value, &weighted) in values.iter_mut().zip(weighted_input) {
+                *value *= self.activation.derivative(weighted);
+            }
+        }
+
+        let mut cost_gradient_weights = vec![vec![T::zero(); self.weights[0].len()]; self.weights.len()];
+        let mut cost_gradient_biases = vec![T::zero(); self.biases.len()];
+        let mut input_gradients = vec![vec![T::zero(); self.input_shape.len()]; node_values.len()];
+        let area = self.output_shape.height * self.output_shape.width;
+
+        for (index, values) in node_values.iter().enumerate() {
+            let input = &self.inputs[index];
+            let input_gradient = &mut input_gradients[index];
+
+            for filter in 0..self.output_shape.channels {
+                cost_gradient_biases[filter] += values[filter * area..(filter + 1) * area].iter().copied().sum();
+
+                let weights = &self.weights[filter];
+                let filter_gradients = &mut cost_gradient_weights[filter];
+                self.for_each_connection(filter, |weight_index, input_index, output_index| {
+                    filter_gradients[weight_index] += input[input_index] * values[output_index];
+                    input_gradient[input_index] += weights[weight_index] * values[output_index];
+                });
+            }
+        }
+
+        self.cost_gradient_weights = cost_gradient_weights;
+        self.cost_gradient_biases = cost_gradient_biases;
+
+        input_gradients
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        let regularization = &self.regularization;
+
+        let mut parameters: Vec<Parameters<'_, T>> = self.weights.iter_mut()
+            .zip(self.cost_gradient_weights.iter_mut())
+            .map(|(values, gradients)| Parameters { values, gradients, regularization: Some(regularization) })
+            .collect();
+
+        parameters.push(Parameters {
+            values: &mut self.biases,
+            gradients: &mut self.cost_gradient_biases,
+            regularization: if regularization.include_biases { Some(regularization) } else { None },
+        });
+
+        parameters
+    }
+
+    fn set_regularization(&mut self, regularization: &Regularization<T>) {
+        self.regularization = regularization.clone();
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut record: Vec<String> = self.input_shape.to_shape().iter().map(|value| value.to_string()).collect();
+        record.extend([self.output_shape.channels, self.kernel_size, self.stride, self.padding].iter().map(|value| value.to_string()));
+        record.push(self.activation_type.name().to_string());
+
+        serialization::write_record(writer, "conv2d", &record)?;
+        for weights in &self.weights {
+            serialization::write_values(writer, "weights", weights)?;
+        }
+        serialization::write_values(writer, "biases", &self.biases)
+    }
+}
+
+impl PoolType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolType::MAX => "max",
+            PoolType::AVERAGE => "average",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "max" => Some(PoolType::MAX),
+            "average" => Some(PoolType::AVERAGE),
+            _ => None,
+        }
+    }
+}
+
+impl Pool2D {
+    pub fn new(input_shape: &[usize], pool_type: &PoolType, size: usize, stride: usize) -> Self {
+        let input_shape = ImageShape::from_shape(input_shape);
+        let output_shape = input_shape.slide(input_shape.channels, size, stride, 0)
+            .unwrap_or_else(|message| panic!("invalid pool2d: {}", message));
+
+        Pool2D {
+            pool_type: *pool_type,
+            input_shape,
+            output_shape,
+            size,
+            stride,
+            max_indices: vec![],
+        }
+    }
+
+    // Input indexes of the window of an output.
+    fn window(&self, channel: usize, out_y: usize, out_x: usize) -> impl Iterator<Item=usize> + '_ {
+        (0..self.size).flat_map(move |y| (0..self.size)
+            .map(move |x| self.input_shape.index(channel, out_y * self.stride + y, out_x * self.stride + x)))
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let record = reader.record("pool2d")?;
+        let value = |index: usize| serialization::parse::<usize>(record.get(index), "pool2d");
+        let pool_type = record.first()
+            .and_then(|name| PoolType::from_name(name))
+            .ok_or_else(|| serialization::invalid_data("unknown pool type".to_string()))?;
+
+        let input_shape = [value(1)?, value(2)?, value(3)?];
+        let (size, stride) = (value(4)?, value(5)?);
+        ImageShape::from_shape(&input_shape).slide(input_shape[0], size, stride, 0)
+            .map_err(|message| serialization::invalid_data(format!("invalid pool2d: {}", message)))?;
+
+        Ok(Pool2D::new(&input_shape, &pool_type, size, stride))
+    }
+}
+
+impl<T: Float> Layer<T> for Pool2D {
+    fn name(&self) -> &'static str {
+        "pool2d"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        self.output_shape.to_shape()
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        let output = self.output_shape;
+        let window_len = T::of((self.size * self.size) as f64);
+
+        self.max_indices = Vec::with_capacity(inputs.len());
+
+        inputs.iter()
+            .map(|input| {
+                let mut outputs = vec![T::zero(); output.len()];
+                let mut max_indices = vec![0; output.len()];
+
+                for channel in 0..output.channels {
+                    for out_y in 0..output.height {
+                        for out_x in 0..output.width {
+                            let output_index = output.index(channel, out_y, out_x);
+
+                            outputs[output_index] = match self.pool_type {
+                                PoolType::MAX => {
+                                    let max_index = self.window(channel, out_y, out_x)
+                                        .reduce(|max, index| if input[index] > input[max] { index } else { max })
+                                        .unwrap();
+                                    max_indices[output_index] = max_index;
+                                    input[max_index]
+                                }
+                                PoolType::AVERAGE => self.window(channel, out_y, out_x).map(|index| input[index]).sum::<T>() / window_len,
+                            };
+                        }
+                    }
+                }
+
+                self.max_indices.push(max_indices);
+                outputs
+            })
+            .collect()
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let output = self.output_shape;
+        let window_len = T::of((self.size * self.size) as f64);
+
+        output_gradients.iter()
+            .enumerate()
+            .map(|(index, gradients)| {
+                let mut input_gradients = vec![T::zero(); self.input_shape.len()];
+
+                for channel in 0..output.channels {
+                    for out_y in 0..output.height {
+                        for out_x in 0..output.width {
+                            let output_index = output.index(channel, out_y, out_x);
+
+                            match self.pool_type {
+                                PoolType::MAX => input_gradients[self.max_indices[index][output_index]] += gradients[output_index],
+                                PoolType::AVERAGE => for input_index in self.window(channel, out_y, out_x) {
+                                    input_gradients[input_index] += gradients[output_index] / window_len;
+                                },
+                            }
+                        }
+                    }
+                }
+
+                input_gradients
+            })
+            .collect()
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut record = vec![self.pool_type.name().to_string()];
+        record.extend(self.input_shape.to_shape().iter().map(|value| value.to_string()));
+        record.push(self.size.to_string());
+        record.push(self.stride.to_string());
+
+        serialization::write_record(writer, "pool2d", &record)
+    }
+}
+
+impl<T: Float> Layer<T> for Flatten {
+    fn name(&self) -> &'static str {
+        "flatten"
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        vec![input_shape.iter().product()]
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        inputs
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        output_gradients
+    }
+
+    fn save(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_point::DataPoint;
+    use crate::dense::Dense;
+    use crate::neural_network::NeuralNetwork;
+
+    // Network for MNIST-sized data: 28x28 grayscale images of 10 classes, the same for every run.
+    fn mnist_network() -> NeuralNetwork {
+        let mut network = NeuralNetwork::sequential(vec![1, 28, 28]);
+        network.add(Box::new(Conv2D::seeded(&network.output_shape(), 8, 3, 1, 1, &ActivationType::RELU, 1)));
+        network.add(Box::new(Pool2D::new(&network.output_shape(), &PoolType::MAX, 2, 2)));
+        network.add(Box::new(Flatten));
+        network.add(Box::new(Dense::seeded(network.output_shape()[0], 10, &ActivationType::SIGMOID, 2)));
+
+        network
+    }
+
+    fn random_image(seed: u64) -> Vec<f32> {
+        snippets::seeded_random_numbers(seed).take(28 * 28).map(f32::unit).collect()
+    }
+
+    #[test]
+    fn trains_on_mnist_sized_images() {
+        let mut network = mnist_network();
+        assert_eq!(network.layers[1].output_shape(&[]), vec![8, 14, 14]);
+
+        let data: Vec<DataPoint> = (0..20)
+            .map(|index| DataPoint::new(random_image(index as u64 + 3), index % 10, 10))
+            .collect();
+        let cost = network.cost(&data);
+        for _ in 0..10 {
+            network.learn(&data, 0.5).unwrap();
+        }
+
+        assert!(network.cost(&data) < cost);
+    }
+
+    #[test]
+    fn saves_loads_and_validates_image_layers() {
+        let mut network = mnist_network();
+        let path = std::env::temp_dir().join(format!("nn-rust-convolution-test-{}.txt", std::process::id()));
+        network.save(&path).unwrap();
+        let mut loaded: NeuralNetwork = NeuralNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let image = random_image(42);
+        assert_eq!(loaded.calculate_outputs(image.clone()), network.calculate_outputs(image));
+
+        let load_conv = |text: &str| Conv2D::<f32>::load(&mut RecordReader::new(text.as_bytes())).map(|_| ());
+        let load_pool = |text: &str| Pool2D::load(&mut RecordReader::new(text.as_bytes())).map(|_| ());
+        // Kernel larger than the padded image, no filters, and a stride of 0.
+        assert!(load_conv("conv2d 1 4 4 2 7 1 1 relu\n").unwrap_err().to_string().contains("does not fit"));
+        assert!(load_conv("conv2d 1 4 4 0 3 1 0 relu\n").is_err());
+        assert!(load_pool("pool2d max 1 4 4 2 0\n").is_err());
+        assert!(std::panic::catch_unwind(|| Pool2D::new(&[1, 2, 2], &PoolType::MAX, 3, 1)).is_err());
+    }
+}
@@ -1,22 +1,51 @@
+use crate::float::Float;
+
 #[derive(Clone)]
-pub struct DataPoint {
-    pub inputs: Vec<f32>,
-    pub expected_outputs: Vec<f32>,
+pub struct DataPoint<T: Float = f32> {
+    pub inputs: Vec<T>,
+    // Integer inputs, e.g. tokens or categories, for networks starting with an `Embedding` layer.
+    pub indices: Vec<usize>,
+    pub expected_outputs: Vec<T>,
     pub label: usize,
 }
 
-impl DataPoint {
-    pub fn new(inputs: Vec<f32>, label: usize, num_labels: usize) -> Self {
+impl<T: Float> DataPoint<T> {
+    pub fn new(inputs: Vec<T>, label: usize, num_labels: usize) -> Self {
         DataPoint {
             inputs,
+            indices: vec![],
+            expected_outputs: Self::create_one_hot(label, num_labels),
+            label,
+        }
+    }
+
+    // Sequence of time steps, with the expected outputs of every time step. Both are stored flat.
+    // The window and the render command only have points, so only the tests build sequences so far.
+    #[allow(dead_code)]
+    pub fn sequence(steps: Vec<Vec<T>>, expected_outputs: Vec<Vec<T>>, label: usize) -> Self {
+        DataPoint {
+            inputs: steps.concat(),
+            indices: vec![],
+            expected_outputs: expected_outputs.concat(),
+            label,
+        }
+    }
+
+    // Integer indices, e.g. tokens or categories, for an `Embedding` layer, instead of input values.
+    // Like sequences, only the tests build them so far.
+    #[allow(dead_code)]
+    pub fn indices(indices: Vec<usize>, label: usize, num_labels: usize) -> Self {
+        DataPoint {
+            inputs: vec![],
+            indices,
             expected_outputs: Self::create_one_hot(label, num_labels),
             label,
         }
     }
 
-    fn create_one_hot(index: usize, num: usize) -> Vec<f32> {
-        let mut one_hot = vec![0.0; num];
-        one_hot[index] = 1.0;
+    fn create_one_hot(index: usize, num: usize) -> Vec<T> {
+        let mut one_hot = vec![T::zero(); num];
+        one_hot[index] = T::one();
 
         one_hot
     }
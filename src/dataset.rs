@@ -0,0 +1,235 @@
+use std::f64::consts::PI;
+
+use crate::data_point::DataPoint;
+use crate::float::Float;
+
+// Padding to apply to minimal and maximal values of the threshold dataset.
+const VALUE_PADDING: f64 = 0.025;
+// Threshold of the threshold dataset.
+const CLASS_THRESHOLD: f64 = 0.3;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DatasetType {
+    // spot_size^0.8 * spike_length^0.8 > 0.3
+    THRESHOLD,
+    // Interleaved spiral arms, one per class.
+    SPIRALS,
+    // A disc inside a ring.
+    CIRCLES,
+    // Diagonal quadrants share a class.
+    XOR,
+    // Two interleaving half circles.
+    MOONS,
+    // Gaussian clusters around a circle, one per class.
+    BLOBS,
+    // 4x4 alternating cells.
+    CHECKERBOARD,
+    // Two halves split by a tilted line.
+    LINEAR,
+}
+
+impl DatasetType {
+    pub const ALL: [DatasetType; 8] = [
+        DatasetType::THRESHOLD,
+        DatasetType::SPIRALS,
+        DatasetType::CIRCLES,
+        DatasetType::XOR,
+        DatasetType::MOONS,
+        DatasetType::BLOBS,
+        DatasetType::CHECKERBOARD,
+        DatasetType::LINEAR,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DatasetType::THRESHOLD => "threshold",
+            DatasetType::SPIRALS => "spirals",
+            DatasetType::CIRCLES => "circles",
+            DatasetType::XOR => "xor",
+            DatasetType::MOONS => "moons",
+            DatasetType::BLOBS => "blobs",
+            DatasetType::CHECKERBOARD => "checkerboard",
+            DatasetType::LINEAR => "linear",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|dataset_type| dataset_type.name() == name)
+    }
+
+    // The following type, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|dataset_type| dataset_type == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // Whether the number of classes can be chosen, the others always have two.
+    pub fn has_classes(&self) -> bool {
+        matches!(self, DatasetType::SPIRALS | DatasetType::BLOBS)
+    }
+}
+
+// Generator of two dimensional classification data in the unit square. The same seed gives the same data.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dataset<T: Float = f32> {
+    pub dataset_type: DatasetType,
+    // Number of data points.
+    pub size: usize,
+    // Standard deviation of the gaussian noise added to the positions, in units of the square.
+    pub noise: T,
+    // Number of classes of the types that support it.
+    pub classes: usize,
+    pub seed: u64,
+}
+
+impl<T: Float> Dataset<T> {
+    pub fn new(dataset_type: DatasetType, size: usize, noise: T, classes: usize, seed: u64) -> Self {
+        Dataset { dataset_type, size, noise, classes, seed }
+    }
+
+    // Same kind of data, but from other random numbers.
+    pub fn validation(&self, size: usize) -> Self {
+        Dataset {
+            size,
+            seed: snippets::xorshift(self.seed),
+            ..*self
+        }
+    }
+
+    pub fn num_labels(&self) -> usize {
+        if self.dataset_type.has_classes() { self.classes.max(2) } else { 2 }
+    }
+
+    pub fn generate(&self) -> Vec<DataPoint<T>> {
+        let mut random = Random::new(self.seed);
+        let classes = self.num_labels();
+        let noise = self.noise.to_f64().unwrap();
+
+        (0..self.size)
+            .map(|i| {
+                // Centered coordinates between -1 and 1.
+                let (x, y, label) = match self.dataset_type {
+                    DatasetType::THRESHOLD => {
+                        let spot_size = VALUE_PADDING + random.uniform() * (1.0 - 2.0 * VALUE_PADDING);
+                        let spike_length = VALUE_PADDING + random.uniform() * (1.0 - 2.0 * VALUE_PADDING);
+                        let poisonous = spot_size.powf(0.8) * spike_length.powf(0.8) > CLASS_THRESHOLD;
+
+                        (spot_size * 2.0 - 1.0, spike_length * 2.0 - 1.0, poisonous as usize)
+                    }
+                    DatasetType::SPIRALS => {
+                        let class = i % classes;
+                        let t = random.uniform();
+                        let angle = class as f64 * 2.0 * PI / classes as f64 + t * 3.0 * PI;
+
+                        (0.9 * t * angle.cos(), 0.9 * t * angle.sin(), class)
+                    }
+                    DatasetType::CIRCLES => {
+                        let class = i % 2;
+                        let radius = if class == 0 { 0.4 * random.uniform() } else { 0.6 + 0.3 * random.uniform() };
+                        let angle = 2.0 * PI * random.uniform();
+
+                        (radius * angle.cos(), radius * angle.sin(), class)
+                    }
+                    DatasetType::XOR => {
+                        let (x, y) = (random.centered(), random.centered());
+
+                        (x, y, ((x > 0.0) != (y > 0.0)) as usize)
+                    }
+                    DatasetType::MOONS => {
+                        let class = i % 2;
+                        let angle = PI * random.uniform();
+                        let (x, y) = if class == 0 { (angle.cos(), angle.sin()) } else { (1.0 - angle.cos(), 0.5 - angle.sin()) };
+
+                        ((x - 0.5) / 1.6, (y - 0.25) / 1.6, class)
+                    }
+                    DatasetType::BLOBS => {
+                        let class = i % classes;
+                        let angle = class as f64 * 2.0 * PI / classes as f64;
+
+                        (0.6 * angle.cos() + 0.15 * random.gaussian(), 0.6 * angle.sin() + 0.15 * random.gaussian(), class)
+                    }
+                    DatasetType::CHECKERBOARD => {
+                        let (x, y) = (random.centered(), random.centered());
+                        let cell = |value: f64| ((value + 1.0) * 2.0).floor() as usize;
+
+                        (x, y, (cell(x) + cell(y)) % 2)
+                    }
+                    DatasetType::LINEAR => {
+                        let (x, y) = (random.centered(), random.centered());
+
+                        (x, y, (y > 0.5 * x) as usize)
+                    }
+                };
+
+                let mut unit = |value: f64| T::of(((value + noise * 2.0 * random.gaussian() + 1.0) / 2.0).clamp(0.0, 1.0));
+                let inputs = vec![unit(x), unit(y)];
+
+                DataPoint::new(inputs, label, classes)
+            })
+            .collect()
+    }
+}
+
+// Seeded xorshift generator.
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random { state: snippets::seeded_random_numbers(seed).next().unwrap() }
+    }
+
+    // Between 0 and 1.
+    fn uniform(&mut self) -> f64 {
+        self.state = snippets::xorshift(self.state);
+        self.state as f64 / u64::MAX as f64
+    }
+
+    // Between -1 and 1.
+    fn centered(&mut self) -> f64 {
+        self.uniform() * 2.0 - 1.0
+    }
+
+    // Standard normal distribution (Box-Muller).
+    fn gaussian(&mut self) -> f64 {
+        let u = self.uniform().max(f64::MIN_POSITIVE);
+        let v = self.uniform();
+
+        (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_seeded_data_in_the_unit_square() {
+        for dataset_type in DatasetType::ALL {
+            let dataset: Dataset<f64> = Dataset::new(dataset_type, 60, 0.05, 3, 42);
+            let data = dataset.generate();
+
+            assert_eq!(data.len(), 60);
+            assert!(data.iter().all(|data_point| data_point.inputs.iter().all(|&input| (0.0..=1.0).contains(&input))));
+            assert!(data.iter().all(|data_point| data_point.expected_outputs.len() == dataset.num_labels()));
+            // Every class is present.
+            for label in 0..dataset.num_labels() {
+                assert!(data.iter().any(|data_point| data_point.label == label), "{} has no class {}", dataset_type.name(), label);
+            }
+
+            let again = dataset.generate();
+            assert!(data.iter().zip(&again).all(|(a, b)| a.inputs == b.inputs && a.label == b.label));
+        }
+
+        assert_eq!(Dataset::<f64>::new(DatasetType::BLOBS, 10, 0.0, 4, 1).num_labels(), 4);
+        assert_eq!(Dataset::<f64>::new(DatasetType::XOR, 10, 0.0, 4, 1).num_labels(), 2);
+
+        // Without noise the quadrants decide the class.
+        for data_point in Dataset::<f64>::new(DatasetType::XOR, 50, 0.0, 2, 7).generate() {
+            let (x, y) = (data_point.inputs[0], data_point.inputs[1]);
+            assert_eq!(data_point.label, ((x > 0.5) != (y > 0.5)) as usize);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::float::Float;
+use crate::neural_network::NeuralNetwork;
+
+// Colors of the classes, repeated if there are more classes.
+pub const PALETTE: [[u8; 3]; 8] = [
+    [0, 0, 255],
+    [255, 0, 0],
+    [0, 200, 0],
+    [255, 200, 0],
+    [200, 0, 255],
+    [0, 220, 220],
+    [255, 120, 0],
+    [255, 105, 180],
+];
+// Color where no class is predicted.
+pub const BACKGROUND: [u8; 3] = [28, 31, 33];
+// Color of the lines where the output of a class crosses 0.5.
+pub const CONTOUR: [u8; 3] = [230, 230, 230];
+
+pub fn class_color(class: usize) -> [u8; 3] {
+    PALETTE[class % PALETTE.len()]
+}
+
+// Rectangle of the two dimensional input space that is shown.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Region<T: Float = f32> {
+    pub x_min: T,
+    pub x_max: T,
+    pub y_min: T,
+    pub y_max: T,
+}
+
+impl<T: Float> Region<T> {
+    pub fn new(x_min: T, x_max: T, y_min: T, y_max: T) -> Self {
+        Region { x_min, x_max, y_min, y_max }
+    }
+
+    // Position of an input as a fraction of the region, from the bottom left corner.
+    pub fn unit_of(&self, x: T, y: T) -> (T, T) {
+        ((x - self.x_min) / (self.x_max - self.x_min), (y - self.y_min) / (self.y_max - self.y_min))
+    }
+
+    // Input at a fraction of the region, from the bottom left corner.
+    pub fn input_at(&self, u: T, v: T) -> (T, T) {
+        (self.x_min + u * (self.x_max - self.x_min), self.y_min + v * (self.y_max - self.y_min))
+    }
+
+    // Scale the region around its center, a factor below 1 zooms in.
+    pub fn zoom(&self, factor: T) -> Self {
+        let half = T::of(0.5);
+        let (x_center, y_center) = self.input_at(half, half);
+        let x_half = (self.x_max - self.x_min) * half * factor;
+        let y_half = (self.y_max - self.y_min) * half * factor;
+
+        Self::new(x_center - x_half, x_center + x_half, y_center - y_half, y_center + y_half)
+    }
+}
+
+// RGBA pixels, rows from top to bottom.
+pub struct BoundaryImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+// Classify the center of every pixel of the region. The colors of the classes are blended by their outputs,
+// fading to the background where the network is not confident, with contour lines where an output crosses 0.5.
+pub fn render<T: Float>(network: &mut NeuralNetwork<T>, region: &Region<T>, width: usize, height: usize) -> BoundaryImage {
+    let inputs: Vec<Vec<T>> = (0..height)
+        .flat_map(|row| (0..width).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let u = T::of((column as f64 + 0.5) / width as f64);
+            let v = T::of(1.0 - (row as f64 + 0.5) / height as f64);
+            let (x, y) = region.input_at(u, v);
+            vec![x, y]
+        })
+        .collect();
+
+    // A single output is the confidence of the second class.
+    let confidences: Vec<Vec<f64>> = network.forward(inputs).into_iter()
+        .map(|outputs| {
+            let outputs: Vec<f64> = outputs.iter().map(|output| output.to_f64().unwrap_or(0.0).clamp(0.0, 1.0)).collect();
+            if outputs.len() == 1 { vec![1.0 - outputs[0], outputs[0]] } else { outputs }
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        for column in 0..width {
+            let index = row * width + column;
+            let color = if on_contour(&confidences, index, column + 1 < width, row + 1 < height, width) {
+                CONTOUR
+            } else {
+                blend(&confidences[index])
+            };
+
+            pixels.extend_from_slice(&color);
+            pixels.push(255);
+        }
+    }
+
+    BoundaryImage { width, height, pixels }
+}
+
+fn blend(confidences: &[f64]) -> [u8; 3] {
+    let total: f64 = confidences.iter().sum();
+    let confidence = confidences.iter().fold(0.0f64, |max, &confidence| max.max(confidence));
+
+    let mut color = [0.0; 3];
+    for (class, &class_confidence) in confidences.iter().enumerate() {
+        let weight = if total > 0.0 { class_confidence / total } else { 0.0 };
+        for (channel, &class_channel) in color.iter_mut().zip(&class_color(class)) {
+            *channel += weight * class_channel as f64;
+        }
+    }
+
+    // Keep the data points visible on top.
+    let alpha = 0.6 * confidence;
+    let mut blended = [0; 3];
+    for ((blended, &channel), &background) in blended.iter_mut().zip(&color).zip(&BACKGROUND) {
+        *blended = (background as f64 * (1.0 - alpha) + channel * alpha).round() as u8;
+    }
+
+    blended
+}
+
+// Whether any class output crosses 0.5 between the pixel and its right or lower neighbour.
+fn on_contour(confidences: &[Vec<f64>], index: usize, has_right: bool, has_below: bool, width: usize) -> bool {
+    let crosses = |other: usize| confidences[index].iter()
+        .zip(&confidences[other])
+        .any(|(&a, &b)| (a >= 0.5) != (b >= 0.5));
+
+    (has_right && crosses(index + 1)) || (has_below && crosses(index + width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationType;
+    use crate::dense::Dense;
+
+    #[test]
+    fn renders_classes_and_contour() {
+        // Class 1 right of x = 0.5, class 0 left of it.
+        let mut dense: Dense<f64> = Dense::new(2, 2, &ActivationType::SIGMOID);
+        dense.weights = vec![vec![-20.0, 20.0], vec![0.0, 0.0]];
+        dense.biases = vec![10.0, -10.0];
+        let mut network = NeuralNetwork::sequential(vec![2]);
+        network.add(Box::new(dense));
+
+        let image = render(&mut network, &Region::new(0.0, 1.0, 0.0, 1.0), 4, 2);
+        let pixel = |row: usize, column: usize| &image.pixels[(row * 4 + column) * 4..(row * 4 + column) * 4 + 3];
+
+        assert_eq!(image.pixels.len(), 4 * 2 * 4);
+        // Blue on the left, red on the right, and the contour where the outputs cross 0.5 between the middle columns.
+        assert!(pixel(0, 0)[2] > pixel(0, 0)[0]);
+        assert!(pixel(1, 3)[0] > pixel(1, 3)[2]);
+        assert_eq!(pixel(0, 1), CONTOUR);
+        assert_eq!(pixel(1, 1), CONTOUR);
+        assert_ne!(pixel(0, 2), CONTOUR);
+
+        assert_eq!(Region::new(0.0, 1.0, 0.0, 2.0).zoom(0.5), Region::new(0.25, 0.75, 0.5, 1.5));
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{self, BufRead, Write};
+
+use crate::activation::{Activation, Activations, ActivationType};
+#[cfg(test)]
+use crate::autodiff::Var;
+use crate::float::Float;
+use crate::layer::{self, Layer, Parameters};
+use crate::regularization::Regularization;
+use crate::serialization::{self, RecordReader};
+
+const WEIGHT_BOUNDARY: f64 = 2.0;
+
+// Fully connected layer followed by an activation function.
+pub struct Dense<T: Float = f32> {
+    pub num_nodes_in: usize,
+    pub num_nodes_out: usize,
+    pub cost_gradient_weights: Vec<Vec<T>>,
+    pub cost_gradient_biases: Vec<T>,
+    pub weights: Vec<Vec<T>>,
+    pub biases: Vec<T>,
+    pub activations: Vec<T>,
+    pub activation_type: ActivationType,
+    pub activation: Box<dyn Activation<T>>,
+    pub regularization: Regularization<T>,
+    // Learn data of the last batch.
+    inputs: Vec<Vec<T>>,
+    weighted_inputs: Vec<Vec<T>>,
+}
+
+impl<T: Float> Dense<T> {
+    // Create the layer.
+    pub fn new(num_nodes_in: usize, num_nodes_out: usize, activation_type: &ActivationType) -> Self {
+        Self::seeded(num_nodes_in, num_nodes_out, activation_type, snippets::random_seed())
+    }
+
+    // Create the layer with the random weights of a seed, so it starts the same every time.
+    pub fn seeded(num_nodes_in: usize, num_nodes_out: usize, activation_type: &ActivationType, seed: u64) -> Self {
+        let cost_gradient_weights = vec![vec![T::zero(); num_nodes_out]; num_nodes_in];
+        let cost_gradient_biases = vec![T::zero(); num_nodes_out];
+
+        let weights = Self::initialize_random_weights(num_nodes_in, num_nodes_out, seed);
+        let biases = vec![T::zero(); num_nodes_out];
+
+        let activations = vec![T::zero(); num_nodes_out];
+
+        Dense {
+            num_nodes_in,
+            num_nodes_out,
+            cost_gradient_weights,
+            cost_gradient_biases,
+            weights,
+            biases,
+            activations,
+            activation_type: *activation_type,
+            activation: Activations::get_activation(activation_type),
+            regularization: Regularization::none(),
+            inputs: vec![],
+            weighted_inputs: vec![],
+        }
+    }
+
+    fn calculate_weighted_inputs(&self, inputs: &[T]) -> Vec<T> {
+        let mut weighted_inputs = self.biases.clone();
+
+        for node_out in 0..self.num_nodes_out {
+            for node_in in 0..self.num_nodes_in {
+                weighted_inputs[node_out] += inputs[node_in] * self.weights[node_in][node_out];
+            }
+        }
+
+        weighted_inputs
+    }
+
+    // The outputs of a single data point on an autodiff tape, with weights and biases given as variables of the same shape.
+    #[cfg(test)]
+    pub fn expression<'t>(&self, weights: &[Vec<Var<'t, T>>], biases: &[Var<'t, T>], inputs: &[Var<'t, T>]) -> Vec<Var<'t, T>> {
+        (0..self.num_nodes_out)
+            .map(|node_out| {
+                let weighted_input = (0..self.num_nodes_in)
+                    .fold(biases[node_out], |sum, node_in| sum + inputs[node_in] * weights[node_in][node_out]);
+
+                self.activation.expression(weighted_input)
+            })
+            .collect()
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let dense = reader.record("dense")?;
+        let num_nodes_in = serialization::parse(dense.first(), "dense")?;
+        let num_nodes_out = serialization::parse(dense.get(1), "dense")?;
+        let activation_type = dense.get(2)
+            .and_then(|name| ActivationType::from_name(name))
+            .ok_or_else(|| serialization::invalid_data("unknown activation type".to_string()))?;
+
+        let mut dense = Dense::new(num_nodes_in, num_nodes_out, &activation_type);
+        for node_in in 0..num_nodes_in {
+            dense.weights[node_in] = layer::read_exact(reader, "weights", num_nodes_out)?;
+        }
+        dense.biases = layer::read_exact(reader, "biases", num_nodes_out)?;
+
+        Ok(dense)
+    }
+
+    // Scale the weights by the number of inputs, so the weighted inputs of wide layers do not saturate the activation.
+    fn initialize_random_weights(num_nodes_in: usize, num_nodes_out: usize, seed: u64) -> Vec<Vec<T>> {
+        let mut weights: Vec<Vec<T>> = Vec::with_capacity(num_nodes_in);
+        let mut random = snippets::seeded_random_numbers(seed);
+
+        let boundary = WEIGHT_BOUNDARY / (num_nodes_in.max(1) as f64).sqrt();
+        let min = T::of(-boundary);
+        let max = T::of(boundary);
+
+        for _ in 0..num_nodes_in {
+            let mut current_weights: Vec<T> = vec![];
+            for _ in 0..num_nodes_out {
+                let random_weight = min + T::unit(random.next().unwrap()) * (max - min);
+                current_weights.push(random_weight);
+            }
+            weights.push(current_weights);
+        }
+
+        weights
+    }
+}
+
+impl<T: Float> Layer<T> for Dense<T> {
+    fn name(&self) -> &'static str {
+        "dense"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        vec![self.num_nodes_out]
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        let weighted_inputs: Vec<Vec<T>> = inputs.iter()
+            .map(|input| self.calculate_weighted_inputs(input))
+            .collect();
+
+        // Apply activation function.
+        let outputs: Vec<Vec<T>> = weighted_inputs.iter()
+            .map(|weighted_input| weighted_input.iter().map(|&value| self.activation.activate(value)).collect())
+            .collect();
+
+        // Save activation values of the last input on the layer.
+        if let Some(activations) = outputs.last() {
+            self.activations = activations.clone();
+        }
+
+        self.inputs = inputs;
+        self.weighted_inputs = weighted_inputs;
+
+        outputs
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let mut node_values = output_gradients;
+
+        for (values, weighted_input) in node_values.iter_mut().zip(&self.weighted_inputs) {
+            for (value, &weighted) in values.iter_mut().zip(weighted_input) {
+                *value *= self.activation.derivative(weighted);
+            }
+        }
+
+        for gradients in &mut self.cost_gradient_weights {
+            gradients.fill(T::zero());
+        }
+        self.cost_gradient_biases.fill(T::zero());
+
+        let mut input_gradients = vec![vec![T::zero(); self.num_nodes_in]; node_values.len()];
+
+        for (index, values) in node_values.iter().enumerate() {
+            for node_out in 0..self.num_nodes_out {
+                self.cost_gradient_biases[node_out] += values[node_out];
+
+                for node_in in 0..self.num_nodes_in {
+                    self.cost_gradient_weights[node_in][node_out] += self.inputs[index][node_in] * values[node_out];
+                    input_gradients[index][node_in] += self.weights[node_in][node_out] * values[node_out];
+                }
+            }
+        }
+
+        input_gradients
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        let regularization = &self.regularization;
+
+        let mut parameters: Vec<Parameters<'_, T>> = self.weights.iter_mut()
+            .zip(self.cost_gradient_weights.iter_mut())
+            .map(|(values, gradients)| Parameters { values, gradients, regularization: Some(regularization) })
+            .collect();
+
+        parameters.push(Parameters {
+            values: &mut self.biases,
+            gradients: &mut self.cost_gradient_biases,
+            regularization: if regularization.include_biases { Some(regularization) } else { None },
+        });
+
+        parameters
+    }
+
+    fn weights(&self) -> Option<&[Vec<T>]> {
+        Some(&self.weights)
+    }
+
+    fn activation_type(&self) -> Option<ActivationType> {
+        Some(self.activation_type)
+    }
+
+    fn set_regularization(&mut self, regularization: &Regularization<T>) {
+        self.regularization = regularization.clone();
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_record(writer, "dense", &[
+            self.num_nodes_in.to_string(),
+            self.num_nodes_out.to_string(),
+            self.activation_type.name().to_string(),
+        ])?;
+        for weights in &self.weights {
+            serialization::write_values(writer, "weights", weights)?;
+        }
+        serialization::write_values(writer, "biases", &self.biases)
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{self, BufRead, Write};
+
+use crate::float::Float;
+use crate::layer::Layer;
+use crate::serialization::{self, RecordReader};
+
+// Inverted dropout: randomly zero activations while training and scale up the kept ones,
+// so nothing has to be rescaled at inference time.
+pub struct Dropout<T: Float = f32> {
+    pub rate: T,
+    // Current state of the mask generator.
+    random: u64,
+    // Masks of the last training batch, already scaled.
+    masks: Vec<Vec<T>>,
+}
+
+impl<T: Float> Dropout<T> {
+    // The rate must be in [0, 1): dropping everything would scale the kept activations by infinity.
+    pub fn new(rate: T, seed: u64) -> Self {
+        assert!(Self::is_valid_rate(rate), "dropout rate {} is not in [0, 1)", rate);
+
+        Dropout {
+            rate,
+            random: snippets::seeded_random_numbers(seed).next().unwrap(),
+            masks: vec![],
+        }
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let rate = reader.values("rate")?;
+        let rate = *rate.first().ok_or_else(|| serialization::invalid_data("missing dropout rate".to_string()))?;
+        if !Self::is_valid_rate(rate) {
+            return Err(serialization::invalid_data(format!("dropout rate {} is not in [0, 1)", rate)));
+        }
+
+        Ok(Dropout::new(rate, snippets::random_seed()))
+    }
+
+    fn is_valid_rate(rate: T) -> bool {
+        rate >= T::zero() && rate < T::one()
+    }
+}
+
+impl<T: Float> Layer<T> for Dropout<T> {
+    fn name(&self) -> &'static str {
+        "dropout"
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        input_shape.to_vec()
+    }
+
+    // Drop activations while training, pass them through unchanged otherwise.
+    fn forward(&mut self, inputs: Vec<Vec<T>>, training: bool) -> Vec<Vec<T>> {
+        let mut outputs = inputs;
+        let keep = T::one() - self.rate;
+
+        self.masks = Vec::with_capacity(outputs.len());
+        for values in outputs.iter_mut() {
+            let mut mask = Vec::with_capacity(values.len());
+            for value in values.iter_mut() {
+                let scale = if training {
+                    self.random = snippets::xorshift(self.random);
+                    let random = T::of(self.random as f64 / u64::MAX as f64);
+
+                    if random < self.rate { T::zero() } else { T::one() / keep }
+                } else {
+                    T::one()
+                };
+
+                *value *= scale;
+                mask.push(scale);
+            }
+            self.masks.push(mask);
+        }
+
+        outputs
+    }
+
+    // Only the kept activations pass their gradients on.
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let mut gradients = output_gradients;
+
+        for (values, mask) in gradients.iter_mut().zip(&self.masks) {
+            for (value, scale) in values.iter_mut().zip(mask) {
+                *value *= *scale;
+            }
+        }
+
+        gradients
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_values(writer, "rate", &[self.rate])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_rates_outside_zero_to_one() {
+        let load = |text: &str| Dropout::<f64>::load(&mut RecordReader::new(text.as_bytes()));
+
+        assert_eq!(load("rate 0.5\n").unwrap().rate, 0.5);
+        assert!(load("rate 1\n").is_err());
+        assert!(load("rate -0.1\n").is_err());
+        assert!(std::panic::catch_unwind(|| Dropout::<f64>::new(1.0, 1)).is_err());
+    }
+}
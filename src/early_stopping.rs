@@ -0,0 +1,112 @@
+use crate::callback::{Callback, Control, TrainingState};
+use crate::float::Float;
+use crate::neural_network::NeuralNetwork;
+
+// Value watched on the validation data to decide when to stop.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Metric {
+    // Lower is better.
+    COST,
+    // Higher is better.
+    ACCURACY,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::COST => "cost",
+            Metric::ACCURACY => "accuracy",
+        }
+    }
+}
+
+// Stops training once the metric has not improved for a number of epochs, keeping the best weights seen and the layer
+// state that goes with them, like the running statistics of batch normalization.
+pub struct EarlyStopping<T: Float = f32> {
+    pub metric: Metric,
+    // Number of epochs without improvement before stopping.
+    pub patience: usize,
+    // Smallest change of the metric that counts as an improvement.
+    pub min_delta: T,
+    pub best: Option<T>,
+    pub best_epoch: usize,
+    pub epochs_without_improvement: usize,
+    best_values: Option<Vec<Vec<Vec<T>>>>,
+}
+
+impl<T: Float> EarlyStopping<T> {
+    pub fn new(metric: Metric, patience: usize, min_delta: T) -> Self {
+        EarlyStopping {
+            metric,
+            patience,
+            min_delta,
+            best: None,
+            best_epoch: 0,
+            epochs_without_improvement: 0,
+            best_values: None,
+        }
+    }
+
+    // Forget everything seen so far, e.g. for a new network.
+    pub fn reset(&mut self) {
+        self.best = None;
+        self.best_epoch = 0;
+        self.epochs_without_improvement = 0;
+        self.best_values = None;
+    }
+
+    // Record the value of the metric on the validation data at the end of an epoch. Returns whether training should stop.
+    pub fn update(&mut self, network: &mut NeuralNetwork<T>, value: T, epoch: usize) -> bool {
+        let improved = match self.best {
+            None => true,
+            Some(best) => match self.metric {
+                Metric::COST => value < best - self.min_delta,
+                Metric::ACCURACY => value > best + self.min_delta,
+            },
+        };
+
+        if improved {
+            self.best = Some(value);
+            self.best_epoch = epoch;
+            self.epochs_without_improvement = 0;
+            self.best_values = Some(network.snapshot());
+        } else {
+            self.epochs_without_improvement += 1;
+        }
+
+        self.epochs_without_improvement >= self.patience
+    }
+
+    // Put the best weights seen and their layer state back into the network.
+    pub fn restore_best(&self, network: &mut NeuralNetwork<T>) {
+        if let Some(best_values) = &self.best_values {
+            network.restore(best_values);
+        }
+    }
+}
+
+// Stops the training on the validation metrics, which requires validation data.
+impl<T: Float> Callback<T> for EarlyStopping<T> {
+    fn on_train_start(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        self.reset();
+
+        Control::Continue
+    }
+
+    fn on_train_end(&mut self, network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) {
+        self.restore_best(network);
+    }
+
+    fn on_epoch_end(&mut self, network: &mut NeuralNetwork<T>, state: &TrainingState<T>) -> Control {
+        let value = match self.metric {
+            Metric::COST => state.validation_cost,
+            Metric::ACCURACY => state.validation_accuracy,
+        };
+
+        match value {
+            Some(value) if self.update(network, value, state.epoch) => Control::Stop,
+            _ => Control::Continue,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, BufRead, Write};
+
+use crate::float::Float;
+use crate::layer::{self, Layer, Parameters};
+use crate::serialization::{self, RecordReader};
+
+// Maps a sequence of integer indices (e.g. tokens or categories) to trainable vectors.
+// The indices are those of the data points, see `DataPoint::indices`.
+pub struct Embedding<T: Float = f32> {
+    pub vocabulary_size: usize,
+    pub dimensions: usize,
+    // One vector per index.
+    pub table: Vec<Vec<T>>,
+    pub cost_gradient_table: Vec<Vec<T>>,
+    // Indexes of the last batch.
+    indices: Vec<Vec<usize>>,
+    // Rows of the table used by the last batch, the only ones with gradients.
+    used_rows: Vec<usize>,
+}
+
+impl<T: Float> Embedding<T> {
+    pub fn new(vocabulary_size: usize, dimensions: usize) -> Self {
+        let table = (0..vocabulary_size)
+            .map(|_| (0..dimensions).map(|_| T::of(-1.0) + T::random() * T::of(2.0)).collect())
+            .collect();
+
+        Embedding {
+            vocabulary_size,
+            dimensions,
+            table,
+            cost_gradient_table: vec![vec![T::zero(); dimensions]; vocabulary_size],
+            indices: vec![],
+            used_rows: vec![],
+        }
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let record = reader.record("embedding")?;
+        let vocabulary_size = serialization::parse(record.first(), "embedding")?;
+        let dimensions = serialization::parse(record.get(1), "embedding")?;
+
+        let mut embedding = Embedding::new(vocabulary_size, dimensions);
+        for row in embedding.table.iter_mut() {
+            *row = layer::read_exact(reader, "vector", dimensions)?;
+        }
+
+        Ok(embedding)
+    }
+}
+
+impl<T: Float> Layer<T> for Embedding<T> {
+    fn name(&self) -> &'static str {
+        "embedding"
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        vec![input_shape.iter().product(), self.dimensions]
+    }
+
+    fn forward(&mut self, _inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        unreachable!("an embedding takes the indices of the data points, not values")
+    }
+
+    fn takes_indices(&self) -> bool {
+        true
+    }
+
+    fn check_indices(&self, indices: &[usize]) -> Result<(), String> {
+        match indices.iter().find(|&&index| index >= self.vocabulary_size) {
+            Some(index) => Err(format!("embedding index {} out of range 0..{}", index, self.vocabulary_size)),
+            None => Ok(()),
+        }
+    }
+
+    fn forward_indices(&mut self, indices: &[Vec<usize>], _training: bool) -> Vec<Vec<T>> {
+        self.indices = indices.to_vec();
+
+        let mut used_rows: Vec<usize> = self.indices.iter().flatten().copied().collect();
+        used_rows.sort_unstable();
+        used_rows.dedup();
+        self.used_rows = used_rows;
+
+        self.indices.iter()
+            .map(|indices| indices.iter().flat_map(|&index| self.table[index].iter().copied()).collect())
+            .collect()
+    }
+
+    // The inputs are indices, so no gradients are passed on.
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        for &row in &self.used_rows {
+            self.cost_gradient_table[row].fill(T::zero());
+        }
+
+        for (indices, gradients) in self.indices.iter().zip(&output_gradients) {
+            for (&index, vector_gradients) in indices.iter().zip(gradients.chunks(self.dimensions)) {
+                for (gradient, &vector_gradient) in self.cost_gradient_table[index].iter_mut().zip(vector_gradients) {
+                    *gradient += vector_gradient;
+                }
+            }
+        }
+
+        self.indices.iter()
+            .map(|indices| vec![T::zero(); indices.len()])
+            .collect()
+    }
+
+    // Only the rows used by the last batch, so updates stay sparse.
+    // The table is not regularized, which would touch every row on every update.
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        let mut used_rows = self.used_rows.iter().peekable();
+
+        self.table.iter_mut()
+            .zip(self.cost_gradient_table.iter_mut())
+            .enumerate()
+            .filter(|(row, _)| used_rows.next_if_eq(&row).is_some())
+            .map(|(_, (values, gradients))| Parameters { values, gradients, regularization: None })
+            .collect()
+    }
+
+    fn values(&mut self) -> Vec<&mut [T]> {
+        self.table.iter_mut().map(|row| row.as_mut_slice()).collect()
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_record(writer, "embedding", &[self.vocabulary_size.to_string(), self.dimensions.to_string()])?;
+
+        for row in &self.table {
+            serialization::write_values(writer, "vector", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationType;
+    use crate::convolution::Flatten;
+    use crate::data_point::DataPoint;
+    use crate::dense::Dense;
+    use crate::neural_network::NeuralNetwork;
+
+    #[test]
+    fn updates_only_used_rows() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2]);
+        network.add(Box::new(Embedding::new(6, 3)));
+        network.add(Box::new(Flatten));
+        network.add(Box::new(Dense::new(6, 2, &ActivationType::SIGMOID)));
+
+        let mut before = vec![];
+        network.layers[0].save(&mut before).unwrap();
+        network.learn(&vec![DataPoint::indices(vec![1, 4], 0, 2), DataPoint::indices(vec![4, 4], 1, 2)], 0.5).unwrap();
+        let mut after = vec![];
+        network.layers[0].save(&mut after).unwrap();
+
+        // The first line is the header, then one line per row of the table.
+        let changed: Vec<usize> = String::from_utf8(before).unwrap().lines().zip(String::from_utf8(after).unwrap().lines())
+            .skip(1)
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(row, _)| row)
+            .collect();
+        assert_eq!(changed, vec![1, 4]);
+    }
+
+    #[test]
+    fn saves_and_loads_embedding_table() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![3]);
+        network.add(Box::new(Embedding::new(10, 4)));
+        network.add(Box::new(Flatten));
+        network.add(Box::new(Dense::new(12, 2, &ActivationType::SIGMOID)));
+
+        let data = vec![DataPoint::indices(vec![1, 5, 9], 0, 2), DataPoint::indices(vec![2, 5, 0], 1, 2)];
+        network.learn(&data, 0.5).unwrap();
+
+        let path = std::env::temp_dir().join(format!("nn-rust-embedding-test-{}.txt", std::process::id()));
+        network.save(&path).unwrap();
+        let mut loaded: NeuralNetwork<f64> = NeuralNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(network.forward_data(&data), loaded.forward_data(&data));
+    }
+
+    #[test]
+    fn reports_invalid_indices() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2]);
+        network.add(Box::new(Embedding::new(5, 2)));
+        network.add(Box::new(Flatten));
+        network.add(Box::new(Dense::new(4, 2, &ActivationType::SIGMOID)));
+
+        let error = network.learn(&vec![DataPoint::indices(vec![1, 5], 0, 2)], 0.5).unwrap_err();
+        assert_eq!(error.to_string(), "invalid input: data point 0: embedding index 5 out of range 0..5");
+        assert!(network.check_inputs(&[DataPoint::new(vec![1.0, 2.0], 0, 2)]).is_err());
+        assert!(network.check_inputs(&[DataPoint::indices(vec![0, 4], 1, 2)]).is_ok());
+    }
+}
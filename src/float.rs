@@ -0,0 +1,27 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
+
+use num_traits::FromPrimitive;
+
+// Floating point precision the network types are generic over (f32 for speed, f64 for numerics).
+pub trait Float: num_traits::Float + FromPrimitive + AddAssign + SubAssign + MulAssign + DivAssign + Sum + Debug + Display + Default + 'static {
+    // Convert a constant into the float type.
+    fn of(value: f64) -> Self {
+        Self::from_f64(value).unwrap()
+    }
+
+    // Get a random float value between 0 and 1.
+    fn random() -> Self {
+        Self::unit(snippets::random_numbers().next().unwrap())
+    }
+
+    // Map a random number to a float value between 0 and 1, e.g. one of `snippets::seeded_random_numbers`.
+    fn unit(random: u64) -> Self {
+        Self::of(random as f64 / u64::MAX as f64)
+    }
+}
+
+impl Float for f32 {}
+
+impl Float for f64 {}
@@ -0,0 +1,370 @@
+use std::f32::consts::{E, PI};
+use std::fmt;
+
+// Names of the variables, in the order of the dimensions of a point.
+const VARIABLES: [&str; 2] = ["x", "y"];
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Function {
+    SIN,
+    COS,
+    TAN,
+    TANH,
+    EXP,
+    LN,
+    SQRT,
+    ABS,
+}
+
+impl Function {
+    pub const ALL: [Function; 8] = [
+        Function::SIN,
+        Function::COS,
+        Function::TAN,
+        Function::TANH,
+        Function::EXP,
+        Function::LN,
+        Function::SQRT,
+        Function::ABS,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::SIN => "sin",
+            Function::COS => "cos",
+            Function::TAN => "tan",
+            Function::TANH => "tanh",
+            Function::EXP => "exp",
+            Function::LN => "ln",
+            Function::SQRT => "sqrt",
+            Function::ABS => "abs",
+        }
+    }
+
+    // The function with a name, log being another name of ln.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "log" => Some(Function::LN),
+            _ => Self::ALL.iter().copied().find(|function| function.name() == name),
+        }
+    }
+
+    // Value and derivative at x.
+    fn apply(&self, x: f32) -> (f32, f32) {
+        match self {
+            Function::SIN => (x.sin(), x.cos()),
+            Function::COS => (x.cos(), -x.sin()),
+            Function::TAN => (x.tan(), 1.0 / (x.cos() * x.cos())),
+            Function::TANH => (x.tanh(), 1.0 - x.tanh() * x.tanh()),
+            Function::EXP => (x.exp(), x.exp()),
+            Function::LN => (x.ln(), 1.0 / x),
+            Function::SQRT => (x.sqrt(), 0.5 / x.sqrt()),
+            Function::ABS => (x.abs(), x.signum()),
+        }
+    }
+}
+
+// Node of the syntax tree of a formula.
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+enum Node {
+    NUMBER(f32),
+    // Index of the variable in a point.
+    VARIABLE(usize),
+    NEGATE(Box<Node>),
+    ADD(Box<Node>, Box<Node>),
+    SUBTRACT(Box<Node>, Box<Node>),
+    MULTIPLY(Box<Node>, Box<Node>),
+    DIVIDE(Box<Node>, Box<Node>),
+    POWER(Box<Node>, Box<Node>),
+    CALL(Function, Box<Node>),
+}
+
+// A value with its derivative along one variable (forward-mode differentiation).
+#[derive(Clone, Copy)]
+struct Dual {
+    value: f32,
+    derivative: f32,
+}
+
+impl Node {
+    fn evaluate(&self, point: &[f32], variable: usize) -> Dual {
+        let dual = |value, derivative| Dual { value, derivative };
+
+        match self {
+            Node::NUMBER(value) => dual(*value, 0.0),
+            Node::VARIABLE(index) => dual(point.get(*index).copied().unwrap_or(0.0), if *index == variable { 1.0 } else { 0.0 }),
+            Node::NEGATE(a) => {
+                let a = a.evaluate(point, variable);
+                dual(-a.value, -a.derivative)
+            }
+            Node::ADD(a, b) | Node::SUBTRACT(a, b) | Node::MULTIPLY(a, b) | Node::DIVIDE(a, b) | Node::POWER(a, b) => {
+                let (a, b) = (a.evaluate(point, variable), b.evaluate(point, variable));
+                match self {
+                    Node::ADD(..) => dual(a.value + b.value, a.derivative + b.derivative),
+                    Node::SUBTRACT(..) => dual(a.value - b.value, a.derivative - b.derivative),
+                    Node::MULTIPLY(..) => dual(a.value * b.value, a.derivative * b.value + a.value * b.derivative),
+                    Node::DIVIDE(..) => dual(a.value / b.value, (a.derivative * b.value - a.value * b.derivative) / (b.value * b.value)),
+                    _ => {
+                        let value = a.value.powf(b.value);
+                        // A constant exponent also works for negative bases, which have no logarithm.
+                        let derivative = if b.derivative == 0.0 {
+                            b.value * a.value.powf(b.value - 1.0) * a.derivative
+                        } else {
+                            value * (b.derivative * a.value.ln() + b.value * a.derivative / a.value)
+                        };
+                        dual(value, derivative)
+                    }
+                }
+            }
+            Node::CALL(function, a) => {
+                let a = a.evaluate(point, variable);
+                let (value, derivative) = function.apply(a.value);
+                dual(value, derivative * a.derivative)
+            }
+        }
+    }
+
+    // Number of variables used, the index of the last one plus 1.
+    fn dimensions(&self) -> usize {
+        match self {
+            Node::NUMBER(_) => 0,
+            Node::VARIABLE(index) => index + 1,
+            Node::NEGATE(a) | Node::CALL(_, a) => a.dimensions(),
+            Node::ADD(a, b) | Node::SUBTRACT(a, b) | Node::MULTIPLY(a, b) | Node::DIVIDE(a, b) | Node::POWER(a, b) => {
+                a.dimensions().max(b.dimensions())
+            }
+        }
+    }
+}
+
+// Why a formula could not be parsed, and at which character.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+// A function of x and y typed in as text, like `0.2*x^4 + 0.1*x^3 - x^2 + 2`. Supports + - * / ^, parentheses,
+// the constants pi and e and the functions of `Function`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Formula {
+    pub source: String,
+    root: Node,
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { chars: source.chars().collect(), position: 0 };
+        let root = parser.expression()?;
+
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            let message = if c == ')' { "unmatched ')'".to_string() } else { format!("expected an operator, found '{}'", c) };
+            return Err(parser.error(message));
+        }
+
+        Ok(Formula { source: source.to_string(), root })
+    }
+
+    // Number of inputs, 1 if only x is used and 2 if y is.
+    pub fn dimensions(&self) -> usize {
+        self.root.dimensions().max(1)
+    }
+
+    pub fn value(&self, point: &[f32]) -> f32 {
+        self.root.evaluate(point, usize::MAX).value
+    }
+
+    // Exact derivatives along every dimension of the point.
+    pub fn gradient(&self, point: &[f32]) -> Vec<f32> {
+        (0..point.len()).map(|variable| self.root.evaluate(point, variable).derivative).collect()
+    }
+}
+
+// Recursive descent parser, lowest precedence first:
+// expression = term (("+" | "-") term)*
+// term = unary (("*" | "/") unary)*
+// unary = ("-" | "+") unary | power
+// power = primary ("^" unary)?
+// primary = number | constant | variable | function "(" expression ")" | "(" expression ")"
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.term()?;
+        loop {
+            node = match self.next_of(&['+', '-']) {
+                Some('+') => Node::ADD(Box::new(node), Box::new(self.term()?)),
+                Some(_) => Node::SUBTRACT(Box::new(node), Box::new(self.term()?)),
+                None => return Ok(node),
+            };
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.unary()?;
+        loop {
+            node = match self.next_of(&['*', '/']) {
+                Some('*') => Node::MULTIPLY(Box::new(node), Box::new(self.unary()?)),
+                Some(_) => Node::DIVIDE(Box::new(node), Box::new(self.unary()?)),
+                None => return Ok(node),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        match self.next_of(&['-', '+']) {
+            Some('-') => Ok(Node::NEGATE(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.primary()?;
+        match self.next_of(&['^']) {
+            // The exponent is parsed as a unary, which makes ^ right associative and allows 2^-x.
+            Some(_) => Ok(Node::POWER(Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let node = self.expression()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                self.take_while(|c| c.is_ascii_digit() || c == '.');
+                // An exponent like in 1e-3, if the e is followed by digits. Otherwise it is left for the constant e.
+                if matches!(self.peek(), Some('e' | 'E')) {
+                    let sign = matches!(self.chars.get(self.position + 1), Some('+' | '-')) as usize;
+                    if self.chars.get(self.position + 1 + sign).is_some_and(char::is_ascii_digit) {
+                        self.position += 1 + sign;
+                        self.take_while(|c| c.is_ascii_digit());
+                    }
+                }
+
+                let text: String = self.chars[start..self.position].iter().collect();
+                text.parse().map(Node::NUMBER).map_err(|_| ParseError { message: format!("invalid number '{}'", text), position: start })
+            }
+            Some(c) if c.is_alphabetic() => {
+                let name = self.take_while(char::is_alphanumeric);
+                if let Some(index) = VARIABLES.iter().position(|&variable| variable == name) {
+                    return Ok(Node::VARIABLE(index));
+                }
+                match name.as_str() {
+                    "pi" => return Ok(Node::NUMBER(PI)),
+                    "e" => return Ok(Node::NUMBER(E)),
+                    _ => (),
+                }
+
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| ParseError { message: format!("unknown variable or function '{}'", name), position: start })?;
+                self.skip_whitespace();
+                if self.peek() != Some('(') {
+                    return Err(self.error(format!("expected '(' after {}", name)));
+                }
+                self.position += 1;
+                let argument = self.expression()?;
+                self.expect(')')?;
+
+                Ok(Node::CALL(function, Box::new(argument)))
+            }
+            Some(c) => Err(self.error(format!("expected a number, variable, function or '(', found '{}'", c))),
+            None => Err(self.error("unexpected end of the formula".to_string())),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.next_of(&[expected]) {
+            Some(_) => Ok(()),
+            None => Err(self.error(format!("expected '{}'", expected))),
+        }
+    }
+
+    // Consume the next character if it is one of some characters.
+    fn next_of(&mut self, chars: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        let c = self.peek().filter(|c| chars.contains(c))?;
+        self.position += 1;
+
+        Some(c)
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError { message, position: self.position }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_formulas_and_their_derivatives() {
+        let quartic = Formula::parse("0.2*x^4 + 0.1*x^3 - x^2 + 2").unwrap();
+        assert_eq!(quartic.dimensions(), 1);
+        for x in [-2.0, -0.5, 0.0, 1.5] {
+            let expected = 0.2 * x * x * x * x + 0.1 * x * x * x - x * x + 2.0;
+            let slope = 0.8 * x * x * x + 0.3 * x * x - 2.0 * x;
+            assert!((quartic.value(&[x]) - expected).abs() < 1e-5);
+            assert!((quartic.gradient(&[x])[0] - slope).abs() < 1e-4);
+        }
+
+        let formula = Formula::parse("-x^2 * sin(y) + e^(2*x) / 2 - 2^-1").unwrap();
+        assert_eq!(formula.dimensions(), 2);
+        let (x, y) = (0.5f32, 1.2f32);
+        assert!((formula.value(&[x, y]) - (-x * x * y.sin() + (2.0 * x).exp() / 2.0 - 0.5)).abs() < 1e-5);
+        let gradient = formula.gradient(&[x, y]);
+        assert!((gradient[0] - (-2.0 * x * y.sin() + (2.0 * x).exp())).abs() < 1e-4);
+        assert!((gradient[1] - (-x * x * y.cos())).abs() < 1e-5);
+
+        assert_eq!(Formula::parse("1e-3 * x + 2.5E2 + 4e+1").unwrap().value(&[2.0]), 0.002 + 250.0 + 40.0);
+        assert_eq!(Formula::parse("2*e").unwrap().value(&[0.0]), 2.0 * E);
+
+        let error = |source: &str| Formula::parse(source).unwrap_err().to_string();
+        assert_eq!(error(""), "unexpected end of the formula at column 1");
+        assert_eq!(error("2 * (x + 1"), "expected ')' at column 11");
+        assert_eq!(error("x + z"), "unknown variable or function 'z' at column 5");
+        assert_eq!(error("2x"), "expected an operator, found 'x' at column 2");
+        assert_eq!(error("sin x"), "expected '(' after sin at column 5");
+        assert_eq!(error("1.2.3"), "invalid number '1.2.3' at column 1");
+        assert_eq!(error("2e"), "expected an operator, found 'e' at column 2");
+        assert_eq!(error("x)"), "unmatched ')' at column 2");
+    }
+}
@@ -0,0 +1,82 @@
+use crate::float::Float;
+use crate::layer::Parameters;
+
+// Limits on the cost gradients before they are applied, to keep large learn rates from exploding.
+#[derive(Clone)]
+pub struct GradientClipping<T: Float = f32> {
+    // Every gradient is clamped to -value..value.
+    pub value: Option<T>,
+    // The gradients of all parameters together are scaled down to this length.
+    pub global_norm: Option<T>,
+}
+
+impl<T: Float> GradientClipping<T> {
+    pub fn new(value: Option<T>, global_norm: Option<T>) -> Self {
+        GradientClipping { value, global_norm }
+    }
+
+    pub fn none() -> Self {
+        Self::new(None, None)
+    }
+
+    // Clip by value first, then by global norm.
+    pub fn clip(&self, parameters: &mut [Parameters<'_, T>]) {
+        if let Some(value) = self.value {
+            for parameters in parameters.iter_mut() {
+                for gradient in parameters.gradients.iter_mut() {
+                    *gradient = gradient.max(-value).min(value);
+                }
+            }
+        }
+
+        if let Some(global_norm) = self.global_norm {
+            let norm = global_norm_of(parameters);
+
+            if norm > global_norm {
+                let scale = global_norm / norm;
+
+                for parameters in parameters.iter_mut() {
+                    for gradient in parameters.gradients.iter_mut() {
+                        *gradient *= scale;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Length of the gradients of all parameters together.
+pub fn global_norm_of<T: Float>(parameters: &[Parameters<'_, T>]) -> T {
+    parameters.iter()
+        .flat_map(|parameters| parameters.gradients.iter())
+        .map(|&gradient| gradient * gradient)
+        .sum::<T>()
+        .sqrt()
+}
+
+impl<T: Float> Default for GradientClipping<T> {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clips_by_value_then_global_norm() {
+        let mut values = [0.0; 3];
+        let mut gradients = [3.0, -20.0, 0.0];
+        let mut bias_values = [0.0];
+        let mut bias_gradients = [0.0];
+        let mut parameters = vec![
+            Parameters { values: &mut values, gradients: &mut gradients, regularization: None },
+            Parameters { values: &mut bias_values, gradients: &mut bias_gradients, regularization: None },
+        ];
+
+        GradientClipping::new(Some(4.0), Some(2.5)).clip(&mut parameters);
+
+        assert_eq!(gradients, [1.5, -2.0, 0.0]);
+    }
+}
@@ -1,36 +1,413 @@
+use std::f32::consts::PI;
+
+use crate::formula::Formula;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FunctionType {
+    // 0.2x^4 + 0.1x^3 - x^2 + 2, with a local and a global minimum.
+    QUARTIC,
+    // x^2 + y^2
+    BOWL,
+    // An elongated bowl, much steeper along y than along x.
+    ELLIPSE,
+    // A narrow curved valley with the minimum at (1, 1).
+    ROSENBROCK,
+    // Four minima of the same height.
+    HIMMELBLAU,
+    // A bowl covered in local minima.
+    RASTRIGIN,
+}
+
+impl FunctionType {
+    pub const ALL: [FunctionType; 6] = [
+        FunctionType::QUARTIC,
+        FunctionType::BOWL,
+        FunctionType::ELLIPSE,
+        FunctionType::ROSENBROCK,
+        FunctionType::HIMMELBLAU,
+        FunctionType::RASTRIGIN,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FunctionType::QUARTIC => "quartic",
+            FunctionType::BOWL => "bowl",
+            FunctionType::ELLIPSE => "ellipse",
+            FunctionType::ROSENBROCK => "rosenbrock",
+            FunctionType::HIMMELBLAU => "himmelblau",
+            FunctionType::RASTRIGIN => "rastrigin",
+        }
+    }
+
+    // The following type, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|function_type| function_type == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // Number of inputs, 1 or 2.
+    pub fn dimensions(&self) -> usize {
+        match self {
+            FunctionType::QUARTIC => 1,
+            _ => 2,
+        }
+    }
+
+    // Part of the input space that is shown and that start points are picked from, as x min, x max, y min and y max.
+    pub fn bounds(&self) -> (f32, f32, f32, f32) {
+        match self {
+            FunctionType::QUARTIC => (-2.5, 2.5, 0.0, 0.0),
+            FunctionType::BOWL | FunctionType::ELLIPSE => (-3.0, 3.0, -3.0, 3.0),
+            FunctionType::ROSENBROCK => (-2.0, 2.0, -1.0, 3.0),
+            FunctionType::HIMMELBLAU => (-5.0, 5.0, -5.0, 5.0),
+            FunctionType::RASTRIGIN => (-5.12, 5.12, -5.12, 5.12),
+        }
+    }
+
+    // Learn rate the optimizers descend the function with at first, small enough for its steepest slopes.
+    pub fn learn_rate(&self) -> f32 {
+        match self {
+            FunctionType::QUARTIC | FunctionType::BOWL => 0.1,
+            FunctionType::ELLIPSE => 0.2,
+            FunctionType::ROSENBROCK => 0.001,
+            FunctionType::HIMMELBLAU => 0.01,
+            FunctionType::RASTRIGIN => 0.002,
+        }
+    }
+
+    pub fn value(&self, point: &[f32]) -> f32 {
+        let x = point[0];
+        let y = point.get(1).copied().unwrap_or(0.0);
+
+        match self {
+            FunctionType::QUARTIC => 0.2 * x.powf(4.0) + 0.1 * x.powf(3.0) - x.powf(2.0) + 2.0,
+            FunctionType::BOWL => x * x + y * y,
+            FunctionType::ELLIPSE => 0.1 * x * x + 2.0 * y * y,
+            FunctionType::ROSENBROCK => (1.0 - x).powi(2) + 100.0 * (y - x * x).powi(2),
+            FunctionType::HIMMELBLAU => (x * x + y - 11.0).powi(2) + (x + y * y - 7.0).powi(2),
+            FunctionType::RASTRIGIN => 20.0 + x * x - 10.0 * (2.0 * PI * x).cos() + y * y - 10.0 * (2.0 * PI * y).cos(),
+        }
+    }
+
+    // Slope along every dimension, approximated with a step of h.
+    pub fn slope(&self, point: &[f32], h: f32) -> Vec<f32> {
+        let output = self.value(point);
+
+        (0..point.len())
+            .map(|dimension| {
+                let mut moved = point.to_vec();
+                moved[dimension] += h;
+
+                (self.value(&moved) - output) / h
+            })
+            .collect()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OptimizerType {
+    // Steps against the slope.
+    SGD,
+    // Steps against a decaying sum of the slopes.
+    MOMENTUM,
+    // Momentum with the slope taken where the momentum is about to step to.
+    NESTEROV,
+    // Steps scaled by a decaying average of the squared slopes.
+    RMSPROP,
+    // Momentum scaled like RMSProp, with bias correction.
+    ADAM,
+}
+
+impl OptimizerType {
+    pub const ALL: [OptimizerType; 5] = [
+        OptimizerType::SGD,
+        OptimizerType::MOMENTUM,
+        OptimizerType::NESTEROV,
+        OptimizerType::RMSPROP,
+        OptimizerType::ADAM,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OptimizerType::SGD => "sgd",
+            OptimizerType::MOMENTUM => "momentum",
+            OptimizerType::NESTEROV => "nesterov",
+            OptimizerType::RMSPROP => "rmsprop",
+            OptimizerType::ADAM => "adam",
+        }
+    }
+}
+
+// An optimizer descending from a start point, with the points it stepped from.
+pub struct Optimizer {
+    pub optimizer_type: OptimizerType,
+    pub position: Vec<f32>,
+    pub past_values: Vec<Vec<f32>>,
+    // A step led to a point that is not finite. The optimizer stays at the last finite point and takes no more steps.
+    pub diverged: bool,
+    // Decaying sum or average of the slopes, and average of the squared slopes.
+    velocity: Vec<f32>,
+    squares: Vec<f32>,
+}
+
+impl Optimizer {
+    pub const MOMENTUM: f32 = 0.9;
+    pub const DECAY: f32 = 0.9;
+    pub const BETA_1: f32 = 0.9;
+    pub const BETA_2: f32 = 0.999;
+    pub const EPSILON: f32 = 1e-8;
+
+    pub fn new(optimizer_type: OptimizerType, position: Vec<f32>) -> Self {
+        Optimizer {
+            optimizer_type,
+            velocity: vec![0.0; position.len()],
+            squares: vec![0.0; position.len()],
+            position,
+            past_values: vec![],
+            diverged: false,
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.past_values.len()
+    }
+
+    // Take one step with the slope at a point given by a function, unless the optimizer diverged.
+    pub fn step<F: Fn(&[f32]) -> Vec<f32>>(&mut self, slope: F, learn_rate: f32) {
+        if self.diverged {
+            return;
+        }
+
+        let slope = match self.optimizer_type {
+            OptimizerType::NESTEROV => {
+                let ahead: Vec<f32> = self.position.iter()
+                    .zip(&self.velocity)
+                    .map(|(value, velocity)| value - learn_rate * Self::MOMENTUM * velocity)
+                    .collect();
+                slope(&ahead)
+            }
+            _ => slope(&self.position),
+        };
+        let t = self.steps() as i32 + 1;
+
+        let mut position = self.position.clone();
+        for (i, slope) in slope.into_iter().enumerate() {
+            let step = match self.optimizer_type {
+                OptimizerType::SGD => slope,
+                OptimizerType::MOMENTUM | OptimizerType::NESTEROV => {
+                    self.velocity[i] = Self::MOMENTUM * self.velocity[i] + slope;
+                    self.velocity[i]
+                }
+                OptimizerType::RMSPROP => {
+                    self.squares[i] = Self::DECAY * self.squares[i] + (1.0 - Self::DECAY) * slope * slope;
+                    slope / (self.squares[i].sqrt() + Self::EPSILON)
+                }
+                OptimizerType::ADAM => {
+                    self.velocity[i] = Self::BETA_1 * self.velocity[i] + (1.0 - Self::BETA_1) * slope;
+                    self.squares[i] = Self::BETA_2 * self.squares[i] + (1.0 - Self::BETA_2) * slope * slope;
+                    let velocity = self.velocity[i] / (1.0 - Self::BETA_1.powi(t));
+                    let squares = self.squares[i] / (1.0 - Self::BETA_2.powi(t));
+                    velocity / (squares.sqrt() + Self::EPSILON)
+                }
+            };
+
+            position[i] -= learn_rate * step;
+        }
+
+        if position.iter().all(|value| value.is_finite()) {
+            self.past_values.push(std::mem::replace(&mut self.position, position));
+        } else {
+            self.diverged = true;
+        }
+    }
+}
+
 pub struct GradientDescent {
-    pub input_value: f32,
+    pub function_type: FunctionType,
+    // Formula typed in by the user, used instead of the function type if set.
+    pub formula: Option<Formula>,
+    // Input all optimizers start from, with one value per dimension of the function.
+    pub start: Vec<f32>,
     pub learn_rate: f32,
     pub h: f32,
-    pub past_values: Vec<f32>,
+    // Optimizers descending side by side on the same function.
+    pub optimizers: Vec<Optimizer>,
 }
 
 impl GradientDescent {
     pub const LEARN_RATE_STEP: f32 = 0.05;
     pub const H_FACTOR: f32 = 10.0;
+    // Part of the input space shown for formulas and their learn rate, as in `FunctionType::bounds` and
+    // `FunctionType::learn_rate`.
+    pub const FORMULA_BOUNDS: (f32, f32, f32, f32) = (-3.0, 3.0, -3.0, 3.0);
+    pub const FORMULA_LEARN_RATE: f32 = 0.05;
 
-    pub fn new(random_value: f32) -> Self {
+    pub fn new(function_type: FunctionType, start: Vec<f32>, optimizer_types: &[OptimizerType]) -> Self {
         GradientDescent {
-            input_value: random_value,
-            learn_rate: 2.3,
+            function_type,
+            formula: None,
+            optimizers: optimizer_types.iter().map(|&optimizer_type| Optimizer::new(optimizer_type, start.clone())).collect(),
+            start,
+            learn_rate: function_type.learn_rate(),
             h: 0.0001,
-            past_values: vec![],
         }
     }
 
-    pub fn function(x: f32) -> f32 {
-        0.2 * x.powf(4.0) + 0.1 * x.powf(3.0) - x.powf(2.0) + 2.0
+    pub fn function(&self, point: &[f32]) -> f32 {
+        match &self.formula {
+            Some(formula) => formula.value(point),
+            None => self.function_type.value(point),
+        }
+    }
+
+    // Slope along every dimension, exact for formulas and approximated with a step of h for the function types.
+    pub fn slope(&self, point: &[f32]) -> Vec<f32> {
+        match &self.formula {
+            Some(formula) => formula.gradient(point),
+            None => self.function_type.slope(point, self.h),
+        }
+    }
+
+    pub fn dimensions(&self) -> usize {
+        match &self.formula {
+            Some(formula) => formula.dimensions(),
+            None => self.function_type.dimensions(),
+        }
+    }
+
+    pub fn bounds(&self) -> (f32, f32, f32, f32) {
+        match &self.formula {
+            Some(formula) if formula.dimensions() == 1 => (Self::FORMULA_BOUNDS.0, Self::FORMULA_BOUNDS.1, 0.0, 0.0),
+            Some(_) => Self::FORMULA_BOUNDS,
+            None => self.function_type.bounds(),
+        }
+    }
+
+    pub fn default_learn_rate(&self) -> f32 {
+        match &self.formula {
+            Some(_) => Self::FORMULA_LEARN_RATE,
+            None => self.function_type.learn_rate(),
+        }
+    }
+
+    // Name of the function shown to the user.
+    pub fn name(&self) -> &str {
+        match &self.formula {
+            Some(formula) => &formula.source,
+            None => self.function_type.name(),
+        }
+    }
+
+    // Start all optimizers over from a new point.
+    pub fn restart(&mut self, start: Vec<f32>) {
+        self.start = start;
+        self.set_optimizers(&self.optimizer_types());
+    }
+
+    pub fn optimizer_types(&self) -> Vec<OptimizerType> {
+        self.optimizers.iter().map(|optimizer| optimizer.optimizer_type).collect()
     }
 
-    // Run one iteration of the gradient descent algorithm.
+    // Replace the optimizers, starting from the current start point.
+    pub fn set_optimizers(&mut self, optimizer_types: &[OptimizerType]) {
+        self.optimizers = optimizer_types.iter().map(|&optimizer_type| Optimizer::new(optimizer_type, self.start.clone())).collect();
+    }
+
+    // Run one iteration of every optimizer.
     pub fn learn(&mut self) {
-        println!("gradient descent learn!");
+        let mut optimizers = std::mem::take(&mut self.optimizers);
+        for optimizer in &mut optimizers {
+            optimizer.step(|point| self.slope(point), self.learn_rate);
+        }
+        self.optimizers = optimizers;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descends_near_the_minimum_of_every_function() {
+        let minima: [&[f32]; 6] = [&[-1.7240], &[0.0, 0.0], &[0.0, 0.0], &[1.0, 1.0], &[3.0, 2.0], &[0.0, 0.0]];
+        for (function_type, minimum) in FunctionType::ALL.iter().zip(minima) {
+            assert_eq!(minimum.len(), function_type.dimensions());
+
+            // Start close to the minimum with a learn rate small enough for the steepest of the functions.
+            let start: Vec<f32> = minimum.iter().map(|value| value + 0.05).collect();
+            let mut gradient_descent = GradientDescent::new(*function_type, start, &OptimizerType::ALL);
+            gradient_descent.learn_rate = 0.001;
+            let start_value = gradient_descent.function(&gradient_descent.start);
+            for _ in 0..100 {
+                gradient_descent.learn();
+            }
+
+            for optimizer in &gradient_descent.optimizers {
+                let value = gradient_descent.function(&optimizer.position);
+                assert!(value < start_value, "{} {}", function_type.name(), optimizer.optimizer_type.name());
+                assert_eq!(optimizer.steps(), 100);
+            }
+        }
+    }
+
+    #[test]
+    fn descends_from_the_edge_with_the_default_learn_rate() {
+        for function_type in FunctionType::ALL {
+            let (_, x_max, _, y_max) = function_type.bounds();
+            let mut start = vec![0.8 * x_max, 0.8 * y_max];
+            start.truncate(function_type.dimensions());
+            let mut gradient_descent = GradientDescent::new(function_type, start, &OptimizerType::ALL);
+            let start_value = gradient_descent.function(&gradient_descent.start);
+            for _ in 0..100 {
+                gradient_descent.learn();
+            }
 
-        self.past_values.push(self.input_value);
+            for optimizer in &gradient_descent.optimizers {
+                let value = gradient_descent.function(&optimizer.position);
+                assert!(value < start_value, "{} {}: {}", function_type.name(), optimizer.optimizer_type.name(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_the_last_finite_point() {
+        let mut gradient_descent = GradientDescent::new(FunctionType::ROSENBROCK, vec![-1.5, 2.0], &OptimizerType::ALL);
+        gradient_descent.learn_rate = 1.0;
+        for _ in 0..100 {
+            gradient_descent.learn();
+        }
+
+        let sgd = &gradient_descent.optimizers[0];
+        assert!(sgd.diverged);
+        assert!(sgd.steps() < 100);
+        assert!(sgd.position.iter().all(|value| value.is_finite()));
+        for optimizer in &gradient_descent.optimizers {
+            assert!(optimizer.diverged || optimizer.steps() == 100);
+        }
+    }
+
+    #[test]
+    fn descends_a_formula() {
+        let mut gradient_descent = GradientDescent::new(FunctionType::QUARTIC, vec![1.0, 1.0], &[OptimizerType::SGD]);
+        gradient_descent.formula = Some(Formula::parse("(x - 1)^2 + 2*(y + 0.5)^2").unwrap());
+        gradient_descent.learn_rate = 0.1;
+        for _ in 0..100 {
+            gradient_descent.learn();
+        }
 
-        let delta_output = Self::function(self.input_value + self.h) - Self::function(self.input_value);
-        let slope = delta_output / self.h;
+        let position = &gradient_descent.optimizers[0].position;
+        assert!((position[0] - 1.0).abs() < 1e-3 && (position[1] + 0.5).abs() < 1e-3);
+        assert_eq!(gradient_descent.dimensions(), 2);
+    }
+
+    #[test]
+    fn adam_steps_by_the_learn_rate_at_first() {
+        let mut optimizer = Optimizer::new(OptimizerType::ADAM, vec![2.0, -3.0]);
+        optimizer.step(|point| FunctionType::BOWL.slope(point, 0.0001), 0.1);
 
-        self.input_value -= slope * self.learn_rate;
+        assert!((optimizer.position[0] - 1.9).abs() < 1e-5);
+        assert!((optimizer.position[1] + 2.9).abs() < 1e-5);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,297 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::callback::{Callback, Control, TrainingState};
+use crate::canvas::Canvas;
+use crate::data_point::DataPoint;
+use crate::dataset::{Dataset, DatasetType};
+use crate::decision_boundary::{self, BoundaryImage, Region};
+use crate::history::{self, History, Series};
+use crate::neural_network::NeuralNetwork;
+use crate::recorder::{RecordFormat, Recorder};
+use crate::trainer::Trainer;
+
+pub const USAGE: &str = "usage: render [options]
+  --network <file>       network to load (network.txt)
+  --output <file>        PNG to write (render.png)
+  --dataset <name>       threshold, spirals, circles, xor, moons, blobs, checkerboard or linear
+  --seed <number>        seed of the dataset
+  --noise <number>       noise of the dataset
+  --classes <number>     classes of the spirals and blobs datasets
+  --size <number>        number of data points
+  --width <pixels>       width of the image (800)
+  --height <pixels>      height of the image (600)
+  --epochs <number>      train the network before rendering and plot its history (0)
+  --learn-rate <number>  learn rate of the training (0.5)
+  --batch-size <number>  batch size of the training (16)
+  --record <path>        record the training as a GIF if the path ends in .gif, as PNG frames in a directory otherwise
+  --record-every <steps> training steps between two recorded frames (10)
+  --frame-rate <number>  frames per second of the GIF (10)";
+
+// Colors of the plots, as in the window.
+const COLOR_TRAINING: [u8; 3] = [255, 165, 0];
+const COLOR_VALIDATION: [u8; 3] = [144, 238, 144];
+const POINT_RADIUS: f32 = 5.0;
+const PAD: f32 = 20.0;
+
+// What the render command draws, and where to.
+pub struct Options {
+    pub network: PathBuf,
+    pub output: PathBuf,
+    pub dataset: Dataset,
+    pub validation_size: usize,
+    pub region: Region,
+    pub width: usize,
+    pub height: usize,
+    pub epochs: usize,
+    pub learn_rate: f32,
+    pub batch_size: usize,
+    pub record: Option<PathBuf>,
+    pub record_every: usize,
+    pub frame_rate: u32,
+}
+
+impl Options {
+    pub fn new(dataset: Dataset, validation_size: usize, region: Region) -> Self {
+        Options {
+            network: PathBuf::from("network.txt"),
+            output: PathBuf::from("render.png"),
+            dataset,
+            validation_size,
+            region,
+            width: 800,
+            height: 600,
+            epochs: 0,
+            learn_rate: 0.5,
+            batch_size: 16,
+            record: None,
+            record_every: 10,
+            frame_rate: 10,
+        }
+    }
+
+    // Override the options from `--name value` arguments.
+    pub fn parse(&mut self, args: &[String]) -> io::Result<()> {
+        let mut args = args.iter();
+        while let Some(name) = args.next() {
+            let value = args.next().ok_or_else(|| invalid_input(format!("missing value of {}", name)))?;
+            match name.as_str() {
+                "--network" => self.network = PathBuf::from(value),
+                "--output" => self.output = PathBuf::from(value),
+                "--dataset" => {
+                    self.dataset.dataset_type = DatasetType::from_name(value)
+                        .ok_or_else(|| invalid_input(format!("unknown dataset {}", value)))?
+                }
+                "--seed" => self.dataset.seed = parse(name, value)?,
+                "--noise" => self.dataset.noise = parse(name, value)?,
+                "--classes" => self.dataset.classes = parse(name, value)?,
+                "--size" => self.dataset.size = parse(name, value)?,
+                "--width" => self.width = parse(name, value)?,
+                "--height" => self.height = parse(name, value)?,
+                "--epochs" => self.epochs = parse(name, value)?,
+                "--learn-rate" => self.learn_rate = parse(name, value)?,
+                "--batch-size" => self.batch_size = parse(name, value)?,
+                "--record" => self.record = Some(PathBuf::from(value)),
+                "--record-every" => self.record_every = parse(name, value)?,
+                "--frame-rate" => self.frame_rate = parse(name, value)?,
+                _ => return Err(invalid_input(format!("unknown option {}", name))),
+            }
+        }
+
+        if self.width == 0 || self.height == 0 || self.batch_size == 0 || self.record_every == 0 || self.frame_rate == 0 {
+            return Err(invalid_input("width, height, batch size, record every and frame rate must not be 0".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+// Collects the history while the render command trains, and records frames of the training if asked.
+struct Session<'a> {
+    data: &'a [DataPoint],
+    region: Region,
+    history: History,
+    recorder: Option<Recorder>,
+    // First error recording a frame, which stops the training.
+    error: Option<io::Error>,
+}
+
+impl Callback<f32> for Session<'_> {
+    fn on_epoch_end(&mut self, _network: &mut NeuralNetwork, state: &TrainingState) -> Control {
+        self.history.record(state);
+
+        Control::Continue
+    }
+
+    fn on_batch_end(&mut self, network: &mut NeuralNetwork, state: &TrainingState) -> Control {
+        let recorder = match &mut self.recorder {
+            Some(recorder) => recorder,
+            None => return Control::Continue,
+        };
+
+        match recorder.capture(network, self.data, Some(&self.history), &self.region, state.step) {
+            Ok(_) => Control::Continue,
+            Err(error) => {
+                self.error = Some(error);
+                Control::Stop
+            }
+        }
+    }
+}
+
+// Load the network, train it if asked and write its decision boundary, the data and the training history to a PNG.
+pub fn run(options: &Options) -> io::Result<()> {
+    let mut network = NeuralNetwork::load(&options.network)?;
+    if network.input_shape != vec![2] || network.output_shape() != vec![options.dataset.num_labels()] {
+        return Err(invalid_input(format!(
+            "the network maps {:?} to {:?} values, the {} dataset needs 2 to {}",
+            network.input_shape, network.output_shape(), options.dataset.dataset_type.name(), options.dataset.num_labels(),
+        )));
+    }
+
+    let data = options.dataset.generate();
+    let validation_data = options.dataset.validation(options.validation_size).generate();
+
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, RecordFormat::of_path(path), options.record_every, options.frame_rate, options.width, options.height)?),
+        None => None,
+    };
+    let mut session = Session {
+        data: &data,
+        region: options.region,
+        history: History::new(options.epochs.max(1)),
+        recorder,
+        error: None,
+    };
+
+    if options.epochs > 0 {
+        Trainer::new(options.learn_rate, options.batch_size, options.epochs)
+            .train(&mut network, &data, &validation_data, &mut [&mut session])
+            .map_err(|error| io::Error::other(error.to_string()))?;
+    }
+    if let Some(error) = session.error {
+        return Err(error);
+    }
+    if let Some(recorder) = &mut session.recorder {
+        println!("recorded {} frames to {}", recorder.finish(), recorder.path.display());
+    }
+
+    let history = if options.epochs > 0 { Some(&session.history) } else { None };
+    let canvas = render_view(&mut network, &data, history, &options.region, options.width, options.height);
+    save_png(canvas.image(), &options.output)?;
+    println!("rendered {} to {}", options.network.display(), options.output.display());
+
+    Ok(())
+}
+
+// The decision boundary with the data points on top, and the plots of the history in the bottom right quarter.
+pub fn render_view(
+    network: &mut NeuralNetwork,
+    data: &[DataPoint],
+    history: Option<&History>,
+    region: &Region,
+    width: usize,
+    height: usize,
+) -> Canvas {
+    let mut canvas = Canvas::from_image(decision_boundary::render(network, region, width, height));
+
+    for data_point in data {
+        let (u, v) = region.unit_of(data_point.inputs[0], data_point.inputs[1]);
+        let (x, y) = (u * width as f32, (1.0 - v) * height as f32);
+        let [r, g, b] = decision_boundary::class_color(data_point.label);
+
+        canvas.fill_circle(x, y, POINT_RADIUS + 1.0, [0, 0, 0, 160]);
+        canvas.fill_circle(x, y, POINT_RADIUS, [r, g, b, 255]);
+    }
+
+    if let Some(history) = history {
+        let (width, height) = (width as f32, height as f32);
+        let (left, right) = (width / 2.0 + PAD, width - PAD);
+        let middle = height * 0.75;
+
+        draw_plot(&mut canvas, (left, height / 2.0 + PAD, right, middle - PAD / 2.0), history, &[
+            (history.series(|state| state.training_cost, false), COLOR_TRAINING),
+            (history.series(|state| state.validation_cost, false), COLOR_VALIDATION),
+        ]);
+        draw_plot(&mut canvas, (left, middle + PAD / 2.0, right, height - PAD), history, &[
+            (history.series(|state| state.training_accuracy, false), COLOR_TRAINING),
+            (history.series(|state| state.validation_accuracy, false), COLOR_VALIDATION),
+        ]);
+    }
+
+    canvas
+}
+
+pub fn save_png(image: BoundaryImage, path: &Path) -> io::Result<()> {
+    let image = nannou::image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.pixels).unwrap();
+
+    image.save_with_format(path, nannou::image::ImageFormat::Png)
+        .map_err(io::Error::other)
+}
+
+// Draw series of values by step into a rectangle given by its left, top, right and bottom, scaled to fit all of them.
+fn draw_plot(canvas: &mut Canvas, rect: (f32, f32, f32, f32), history: &History, series: &[Series<[u8; 3]>]) {
+    let (left, top, right, bottom) = rect;
+    canvas.fill_rect(left, top, right, bottom, [0, 0, 0, 153]);
+
+    let values: Vec<Vec<(usize, f32)>> = series.iter().map(|(values, _)| values.clone()).collect();
+    let ((first_step, last_step), (min, max)) = match (history.steps(), history::value_range(&values)) {
+        (Some(steps), Some(range)) => (steps, range),
+        _ => return,
+    };
+
+    let step_range = (last_step - first_step).max(1) as f32;
+    for (values, [r, g, b]) in series {
+        let points: Vec<(f32, f32)> = values.iter()
+            .map(|&(step, value)| (
+                left + (step - first_step) as f32 / step_range * (right - left),
+                bottom - (value - min) / (max - min) * (bottom - top),
+            ))
+            .collect();
+
+        canvas.polyline(&points, 2.0, [*r, *g, *b, 255]);
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| invalid_input(format!("invalid value of {}: {}", name, value)))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::TrainingState;
+
+    #[test]
+    fn renders_data_points_and_history() {
+        let mut network = NeuralNetwork::new(vec![2, 2], &crate::activation::ActivationType::SIGMOID);
+        let data = vec![DataPoint::new(vec![0.25, 0.75], 1, 2)];
+        let mut history = History::new(10);
+        for step in 1..=3 {
+            history.record(&TrainingState { step, training_cost: Some(1.0 / step as f32), ..TrainingState::default() });
+        }
+
+        let canvas = render_view(&mut network, &data, Some(&history), &Region::new(0.0, 1.0, 0.0, 1.0), 200, 200);
+        let pixel = |x: usize, y: usize| canvas.pixels[(y * 200 + x) * 4..(y * 200 + x) * 4 + 3].to_vec();
+
+        assert_eq!(canvas.pixels.len(), 200 * 200 * 4);
+        // The data point is drawn at a quarter from the left and from the top.
+        assert_eq!(pixel(50, 50), decision_boundary::class_color(1).to_vec());
+        // The training cost starts in the top left corner of its plot, in the bottom right quarter.
+        assert_eq!(pixel(121, 121), COLOR_TRAINING.to_vec());
+        assert_ne!(pixel(60, 121), COLOR_TRAINING.to_vec());
+
+        let mut options = Options::new(Dataset::new(DatasetType::THRESHOLD, 10, 0.0, 2, 1), 5, Region::new(0.0, 1.0, 0.0, 1.0));
+        let args: Vec<String> = ["--dataset", "moons", "--epochs", "3"].iter().map(|arg| arg.to_string()).collect();
+        options.parse(&args).unwrap();
+        assert_eq!(options.dataset.dataset_type, DatasetType::MOONS);
+        assert_eq!(options.epochs, 3);
+        assert!(options.parse(&["--width".to_string()]).is_err());
+        assert!(options.parse(&["--dataset".to_string(), "unknown".to_string()]).is_err());
+    }
+}
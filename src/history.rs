@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+
+use crate::callback::{Callback, Control, TrainingState};
+use crate::float::Float;
+use crate::neural_network::NeuralNetwork;
+
+// Metrics at the end of every epoch, e.g. for plotting the training progress.
+pub struct History<T: Float = f32> {
+    // Number of epochs kept, the oldest are dropped first.
+    pub capacity: usize,
+    pub records: VecDeque<TrainingState<T>>,
+}
+
+impl<T: Float> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn record(&mut self, state: &TrainingState<T>) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(state.clone());
+    }
+
+    // Values of a metric by step, on a log10 scale if asked. Values that can not be shown are left out.
+    pub fn series<F>(&self, metric: F, log_scale: bool) -> Vec<(usize, T)>
+    where
+        F: Fn(&TrainingState<T>) -> Option<T>,
+    {
+        self.records.iter()
+            .filter_map(|state| {
+                let value = metric(state)?;
+                let value = if log_scale { value.log10() } else { value };
+
+                Some((state.step, value)).filter(|(_, value)| value.is_finite())
+            })
+            .collect()
+    }
+
+    // First and last step of the records.
+    pub fn steps(&self) -> Option<(usize, usize)> {
+        Some((self.records.front()?.step, self.records.back()?.step))
+    }
+}
+
+// Values of a metric by step and the color of their line in a plot.
+pub type Series<C> = (Vec<(usize, f32)>, C);
+
+// Smallest and largest value of all series, widened around the value if they are equal.
+pub fn value_range<T: Float>(series: &[Vec<(usize, T)>]) -> Option<(T, T)> {
+    let mut values = series.iter().flatten().map(|&(_, value)| value);
+    let first = values.next()?;
+    let (min, max) = values.fold((first, first), |(min, max), value| (min.min(value), max.max(value)));
+
+    if max - min > T::epsilon() {
+        Some((min, max))
+    } else {
+        let margin = T::max(min.abs() * T::of(0.1), T::of(0.5));
+        Some((min - margin, max + margin))
+    }
+}
+
+impl<T: Float> Callback<T> for History<T> {
+    fn on_epoch_end(&mut self, _network: &mut NeuralNetwork<T>, state: &TrainingState<T>) -> Control {
+        self.record(state);
+
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_latest_records_and_scales_series() {
+        let mut history: History<f64> = History::new(3);
+        for step in 1..=5 {
+            history.record(&TrainingState {
+                step,
+                training_cost: Some(10f64.powi(step as i32 - 3)),
+                validation_cost: if step == 4 { None } else { Some(0.0) },
+                ..TrainingState::default()
+            });
+        }
+
+        assert_eq!(history.steps(), Some((3, 5)));
+
+        let training = history.series(|state| state.training_cost, true);
+        assert_eq!(training, vec![(3, 0.0), (4, 1.0), (5, 2.0)]);
+        // Zero can not be shown on a log scale.
+        assert!(history.series(|state| state.validation_cost, true).is_empty());
+        assert_eq!(history.series(|state| state.validation_cost, false), vec![(3, 0.0), (5, 0.0)]);
+
+        assert_eq!(value_range(&[training]), Some((0.0, 2.0)));
+        assert_eq!(value_range::<f64>(&[vec![(1, 0.0)]]), Some((-0.5, 0.5)));
+        assert_eq!(value_range::<f64>(&[vec![]]), None);
+    }
+}
@@ -1,93 +1,120 @@
-use snippets;
+use std::io::{self, BufRead, Write};
+
+use crate::activation::ActivationType;
+use crate::convolution::{Conv2D, Flatten, Pool2D};
+use crate::dense::Dense;
+use crate::dropout::Dropout;
+use crate::embedding::Embedding;
+use crate::float::Float;
+use crate::normalization::{BatchNorm, LayerNorm};
+use crate::recurrent::{Recurrent, TimeDistributed};
+use crate::regularization::Regularization;
+use crate::serialization::{self, RecordReader};
+
+// A group of trainable values together with their cost gradients.
+pub struct Parameters<'a, T: Float> {
+    pub values: &'a mut [T],
+    pub gradients: &'a mut [T],
+    // Penalty on the values, if they are regularized.
+    pub regularization: Option<&'a Regularization<T>>,
+}
 
-use crate::activation::Activation;
+// A step of a sequential network. Layers work on batches: one vector of values per data point.
+pub trait Layer<T: Float> {
+    // Name the layer is saved under.
+    fn name(&self) -> &'static str;
 
-const WEIGHT_BOUNDARY: f32 = 2.0;
+    // Shape of the values of a single data point coming out of the layer.
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize>;
 
-#[derive(Clone)]
-pub struct Layer {
-    pub num_nodes_in: usize,
-    pub num_nodes_out: usize,
-    pub cost_gradient_weights: Vec<Vec<f32>>,
-    pub cost_gradient_biases: Vec<f32>,
-    pub weights: Vec<Vec<f32>>,
-    pub biases: Vec<f32>,
-    pub activations: Vec<f32>,
-}
+    // Calculate the outputs for a batch of inputs, remembering what is needed for the backward pass.
+    fn forward(&mut self, inputs: Vec<Vec<T>>, training: bool) -> Vec<Vec<T>>;
+
+    // Whether the layer takes the integer indices of the data points instead of values, like `Embedding`. It has to be
+    // the first layer then.
+    fn takes_indices(&self) -> bool {
+        false
+    }
 
-impl Layer {
-    // Create the layer.
-    pub fn new(num_nodes_in: usize, num_nodes_out: usize) -> Self {
-        let cost_gradient_weights = vec![vec![0.0; num_nodes_out]; num_nodes_in];
-        let cost_gradient_biases = vec![0.0; num_nodes_out];
-
-        let weights = Self::initialize_random_weights(num_nodes_in, num_nodes_out);
-        let biases = vec![0.0; num_nodes_out];
-
-        let activations = vec![0.0; num_nodes_out];
-
-        Layer {
-            num_nodes_in,
-            num_nodes_out,
-            cost_gradient_weights,
-            cost_gradient_biases,
-            weights,
-            biases,
-            activations,
-        }
+    // Why indices of a data point can not be taken by the layer, if they can not.
+    fn check_indices(&self, _indices: &[usize]) -> Result<(), String> {
+        Ok(())
     }
 
-    // Update the weights and biases based on the cost gradients (gradient descent).
-    pub fn apply_gradients(&mut self, learn_rate: f32) {
-        for node_out in 0..self.num_nodes_out {
-            self.biases[node_out] -= self.cost_gradient_biases[node_out] * learn_rate;
+    // Calculate the outputs for a batch of indices, like `forward` for values.
+    fn forward_indices(&mut self, _indices: &[Vec<usize>], _training: bool) -> Vec<Vec<T>> {
+        unreachable!("the {} layer takes values, not indices", self.name())
+    }
+
+    // Calculate the cost gradients of the last batch, based on the gradients of the cost with respect to the outputs.
+    // Returns the gradients of the cost with respect to the inputs.
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>>;
 
-            for node_in in 0..self.num_nodes_in {
-                self.weights[node_in][node_out] -= self.cost_gradient_weights[node_in][node_out] * learn_rate;
-            }
-        }
+    // Trainable values of the layer.
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        vec![]
     }
 
-    // Calculated the output of the layer.
-    pub fn calculate_outputs(&mut self, inputs: Vec<f32>, activation: &Box<dyn Activation>) {
-        for node_out in 0..self.num_nodes_out {
-            let mut weighted_input = self.biases[node_out];
-            for node_in in 0..self.num_nodes_in {
-                weighted_input += inputs[node_in] * self.weights[node_in][node_out];
-            }
-
-            // Apply activation function.
-            let activation = activation.activate(weighted_input);
-            // Save activation value on layer.
-            self.activations[node_out] = activation;
-        }
+    // All trainable values, also those left out of `parameters` to keep updates sparse.
+    fn values(&mut self) -> Vec<&mut [T]> {
+        self.parameters().into_iter().map(|parameters| parameters.values).collect()
     }
 
-    pub fn node_cost(&self, output_activation: f32, expected_output: f32) -> f32 {
-        let error = output_activation - expected_output;
+    // Values that are not trained but change while training, like the running statistics of batch normalization.
+    // They are part of the snapshots of the network.
+    fn state(&mut self) -> Vec<&mut [T]> {
+        vec![]
+    }
 
-        error * error
+    // Weights from every input node to every output node, for fully connected layers.
+    fn weights(&self) -> Option<&[Vec<T>]> {
+        None
     }
 
-    fn node_cost_derivative(&self, output_activation: f32, expected_output: f32) -> f32 {
-        2.0 * (output_activation - expected_output)
+    // Activation function applied to the outputs, for layers that have one.
+    fn activation_type(&self) -> Option<ActivationType> {
+        None
     }
 
-    fn initialize_random_weights(num_nodes_in: usize, num_nodes_out: usize) -> Vec<Vec<f32>> {
-        let mut weights: Vec<Vec<f32>> = Vec::with_capacity(num_nodes_in);
+    fn set_regularization(&mut self, _regularization: &Regularization<T>) {}
 
-        let min = -WEIGHT_BOUNDARY;
-        let max = WEIGHT_BOUNDARY;
+    // Write everything needed to restore the layer with `load`.
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()>;
+}
 
-        for _ in 0..num_nodes_in {
-            let mut current_weights: Vec<f32> = vec![];
-            for _ in 0..num_nodes_out {
-                let random_weight = min + snippets::random_numbers().next().unwrap() as f32 / u64::MAX as f32 * (max - min);
-                current_weights.push(random_weight);
-            }
-            weights.push(current_weights);
-        }
+// Save a layer, preceded by its name.
+pub fn save<T: Float>(layer: &dyn Layer<T>, writer: &mut dyn Write) -> io::Result<()> {
+    serialization::write_record(writer, "layer", &[layer.name().to_string()])?;
 
-        weights
+    layer.save(writer)
+}
+
+// Load a layer saved with `save`.
+pub fn load<T: Float, R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Box<dyn Layer<T>>> {
+    let name = reader.record("layer")?;
+
+    match name.first().map(String::as_str) {
+        Some("dense") => Ok(Box::new(Dense::load(reader)?)),
+        Some("dropout") => Ok(Box::new(Dropout::load(reader)?)),
+        Some("batch_norm") => Ok(Box::new(BatchNorm::load(reader)?)),
+        Some("layer_norm") => Ok(Box::new(LayerNorm::load(reader)?)),
+        Some("conv2d") => Ok(Box::new(Conv2D::load(reader)?)),
+        Some("pool2d") => Ok(Box::new(Pool2D::load(reader)?)),
+        Some("flatten") => Ok(Box::new(Flatten)),
+        Some("recurrent") => Ok(Box::new(Recurrent::load(reader)?)),
+        Some("time_distributed") => Ok(Box::new(TimeDistributed::load(reader)?)),
+        Some("embedding") => Ok(Box::new(Embedding::load(reader)?)),
+        other => Err(serialization::invalid_data(format!("unknown layer '{}'", other.unwrap_or_default()))),
     }
 }
+
+// Read a record which has to have a given number of values.
+pub fn read_exact<T: Float, R: BufRead>(reader: &mut RecordReader<R>, key: &str, len: usize) -> io::Result<Vec<T>> {
+    let values = reader.values(key)?;
+
+    if values.len() != len {
+        return Err(serialization::invalid_data(format!("expected {} values in '{}', found {}", len, key, values.len())));
+    }
+
+    Ok(values)
+}
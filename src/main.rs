@@ -1,35 +1,125 @@
+use std::path::Path;
+
 use nannou::prelude::*;
 
 use snippets;
 
 use crate::activation::ActivationType;
+use crate::callback::{Callback, Control, TrainingState};
+use crate::contour::Grid;
 use crate::data_point::DataPoint;
-use crate::gradient_descent::GradientDescent;
-use crate::neural_network::NeuralNetwork;
+use crate::dataset::{Dataset, DatasetType};
+use crate::decision_boundary::{BoundaryImage, Region};
+use crate::dropout::Dropout;
+use crate::early_stopping::{EarlyStopping, Metric};
+use crate::formula::{Formula, ParseError};
+use crate::gradient_clipping::GradientClipping;
+use crate::gradient_descent::{FunctionType, GradientDescent, Optimizer, OptimizerType};
+use crate::history::{History, Series};
+use crate::metrics_logger::{LogFormat, MetricsLogger};
+use crate::neural_network::{LearnError, NeuralNetwork};
+use crate::normalization::NormalizationType;
+use crate::recorder::{RecordFormat, Recorder};
+use crate::regularization::Regularization;
+use crate::ui::Ui;
 
 mod activation;
+// Only tests build layers and costs on the tape so far, to check the backpropagation against it.
+#[cfg(test)]
+mod autodiff;
+mod callback;
+mod canvas;
+mod contour;
+mod convolution;
 mod data_point;
+mod dataset;
+mod decision_boundary;
+mod dense;
+mod dropout;
+mod early_stopping;
+mod embedding;
+mod float;
+mod formula;
+mod gradient_clipping;
 mod gradient_descent;
+mod headless;
+mod history;
 mod layer;
+mod metrics_logger;
 mod neural_network;
+mod normalization;
+mod recorder;
+mod recurrent;
+mod regularization;
+mod serialization;
+mod trainer;
+mod ui;
 
 // Number of generated entries.
 const ELEMENTS: usize = 65;
-// padding to apply to minimal and maximal values.
-const VALUE_PADDING: f32 = 0.025;
-// Threshold for changing the color of the points on the grid.
-const CLASS_THRESHOLD: f32 = 0.3;
-// Layer configuration.
-const LAYER_CONFIGURATION: [usize; 3] = [2, 3, 2];
+// Number of generated entries to validate on, which are not learned from.
+const VALIDATION_ELEMENTS: usize = 30;
+// Generated dataset, its noise and the number of classes of the datasets that support it.
+const DATASET_TYPE: DatasetType = DatasetType::THRESHOLD;
+const DATASET_NOISE: f32 = 0.0;
+const DATASET_CLASSES: usize = 3;
+// Stepping for the dataset noise, size and number of classes.
+const DATASET_NOISE_STEP: f32 = 0.01;
+const DATASET_SIZE_STEP: usize = 5;
+const DATASET_MAX_CLASSES: usize = 8;
+// Number of inputs, sizes of the initial hidden layers and limits for changing them. The output layer is sized to the
+// number of classes of the dataset.
+const NUM_INPUTS: usize = 2;
+const HIDDEN_LAYERS: [usize; 1] = [3];
+const MAX_HIDDEN_LAYERS: usize = 8;
+const MAX_NEURONS: usize = 32;
 // Activation function.
 const ACTIVATION_TYPE: ActivationType = ActivationType::SIGMOID;
+// Regularization strengths.
+const L1: f32 = 0.0;
+const L2: f32 = 0.0;
+const WEIGHT_DECAY: f32 = 0.0;
+// Gradient clipping by value and by global norm.
+const GRADIENT_CLIP_VALUE: Option<f32> = None;
+const GRADIENT_CLIP_NORM: Option<f32> = None;
+// Stop learning once the validation metric has not improved for a number of frames, keeping the best weights.
+const EARLY_STOPPING_METRIC: Metric = Metric::COST;
+const EARLY_STOPPING_PATIENCE: usize = 300;
+const EARLY_STOPPING_MIN_DELTA: f32 = 0.0001;
+// Dropout rate applied to the outputs of the hidden layers.
+const DROPOUT_RATE: f32 = 0.0;
+// Normalization of the weighted inputs of the hidden layers.
+const NORMALIZATION_TYPE: NormalizationType = NormalizationType::NONE;
+// File the network is saved to and loaded from.
+const NETWORK_FILE: &str = "network.txt";
+// File the training metrics are appended to, one record per frame of learning, with an id for every run.
+const METRICS_FILE: &str = "metrics.csv";
+const METRICS_FORMAT: LogFormat = LogFormat::CSV;
+// Recording of the training, a GIF file or a directory of PNG frames, with a frame every number of learn steps. If it
+// exists, the recording gets a number instead of replacing it.
+const RECORD_PATH: &str = "recording.gif";
+const RECORD_EVERY: usize = 50;
+const RECORD_FRAME_RATE: u32 = 10;
+const RECORD_WIDTH: usize = 400;
+const RECORD_HEIGHT: usize = 300;
 
 // Colors
-const COLOR_SAFE: Srgb<u8> = BLUE;
-const COLOR_UNSAFE: Srgb<u8> = RED;
-
-// Pixel granularity for drawing boundaries.
-const BOUNDARY_STEP: usize = 10;
+const COLOR_TRAINING: Srgb<u8> = ORANGE;
+const COLOR_VALIDATION: Srgb<u8> = LIGHTGREEN;
+const COLOR_POSITIVE: Srgb<u8> = STEELBLUE;
+const COLOR_NEGATIVE: Srgb<u8> = ORANGE;
+
+// Number of frames of learning kept for the plots.
+const HISTORY_CAPACITY: usize = 1000;
+// Distance in pixels from the mouse within which a data point is hovered.
+const HOVER_DISTANCE: f32 = 10.0;
+
+// Window pixels per pixel of the decision boundary texture.
+const BOUNDARY_STEP: usize = 4;
+// Part of the input space shown in the window.
+const INPUT_REGION: Region = Region { x_min: 0.0, x_max: 1.0, y_min: 0.0, y_max: 1.0 };
+// Zooming the input region in or out.
+const ZOOM_FACTOR: f32 = 1.25;
 // Stepping for max chunk size factor.
 const MAX_CHUNK_SIZE_FACTOR_STEP: f32 = 0.1;
 
@@ -38,11 +128,74 @@ const Z_BOUNDRY: f32 = 1.0;
 const Z_POINTS: f32 = 2.0;
 const Z_GRAPH: f32 = 3.0;
 const Z_UI: f32 = 4.0;
+const Z_PANEL: f32 = 5.0;
+
+// Width of the control panel in the top right corner.
+const PANEL_WIDTH: f32 = 240.0;
+// Largest learn rate of the network, with the slider or the keys, and the ranges of the gradient descent learn rate and
+// h sliders.
+const MAX_LEARN_RATE: f32 = 10.0;
+const DESCENT_LEARN_RATE_RANGE: (f32, f32) = (0.0001, 1.0);
+const H_RANGE: (f32, f32) = (0.000001, 1.0);
+// Largest dataset noise and size of their sliders.
+const MAX_DATASET_NOISE: f32 = 0.3;
+const MAX_DATASET_SIZE: usize = 500;
+
+const HELP: &str = "mouse
+  left click: add a point of the selected class, drag a point
+  right click: remove the nearest point, except the last one
+  0-9: select the class
+
+learning
+  space: play / pause    I: learn step
+  up / down: learn rate    K / J: max chunk size factor
+  S: new network    W / O: save / load network
+
+architecture
+  tab: select hidden layer    A / X: add / remove it
+  E / Q: more / fewer neurons    F: next activation
+
+data
+  N: new data    D: next dataset    C: number of classes
+  [ / ]: noise    , / .: size
+
+view
+  P: history    Y: log scale    T: topology
+  + / -: zoom    M: control panel    H: this help
+  V: start / stop recording
+
+gradient descent
+  G: graph    R: new graph    L: learn step    B: next function
+  Z: compare optimizers    left / right: h
+  enter: type a formula of x and y, empty for the function";
 
 // 100 pixel correspond to value 1.0
 const GRAPH_SCALING: f32 = 100.0;
+// Learn rate of the network. The gradient descent graph has its own, which depends on the function.
+const LEARN_RATE: f32 = 2.3;
+// Function of the gradient descent graph.
+const FUNCTION_TYPE: FunctionType = FunctionType::QUARTIC;
+// Optimizer of the graph when not comparing all of them, and the colors of their trails in the order of
+// `OptimizerType::ALL`.
+const OPTIMIZER_TYPE: OptimizerType = OptimizerType::SGD;
+const OPTIMIZER_COLORS: [Srgb<u8>; 5] = [WHITE, ORANGE, LIGHTGREEN, VIOLET, GOLD];
+// Cells per side of the grid the contours of two dimensional functions are traced on, and the number of contours.
+const CONTOUR_RESOLUTION: usize = 80;
+const CONTOUR_LEVELS: usize = 12;
 
 fn main() {
+    // Render to a PNG without a window: `render [options]`.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        let dataset = Dataset::new(DATASET_TYPE, ELEMENTS, DATASET_NOISE, DATASET_CLASSES, 1);
+        let mut options = headless::Options::new(dataset, VALIDATION_ELEMENTS, INPUT_REGION);
+        if let Err(error) = options.parse(&args[1..]).and_then(|()| headless::run(&options)) {
+            eprintln!("{}\n\n{}", error, headless::USAGE);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     nannou::app(model)
         .update(update)
         .view(view)
@@ -60,15 +213,49 @@ struct GridPoint {
 struct Model {
     points: Vec<GridPoint>,
     data: Vec<DataPoint>,
+    validation_data: Vec<DataPoint>,
+    dataset: Dataset,
     network: NeuralNetwork,
+    // Architecture the network is built from, with the hidden layer whose size is changed.
+    hidden_layers: Vec<usize>,
+    selected_layer: usize,
     activation_type: ActivationType,
+    learn_rate: f32,
     gradient_descent: GradientDescent,
-    boundary_predictions: Vec<Vec<Option<usize>>>,
+    // Optimizer chosen for the graph, used when the optimizers are not compared.
+    optimizer_type: OptimizerType,
+    region: Region,
+    boundary: Option<wgpu::Texture>,
     max_chunk_size_factor: f32,
     cost: f32,
     correct: u32,
     learn: bool,
+    // Why the training was stopped, if the network diverged or could not take the data.
+    learn_error: Option<LearnError>,
+    early_stopping: EarlyStopping,
+    // Counters and metrics of the current network. Every frame of learning is an epoch.
+    state: TrainingState,
+    logger: Option<MetricsLogger>,
+    recorder: Option<Recorder>,
+    history: History,
+    show_history: bool,
+    // Plot the costs on a log scale.
+    log_scale: bool,
+    show_topology: bool,
+    // Index of the data point under the mouse and the outputs of every layer for it, preceded by its inputs.
+    hovered: Option<usize>,
+    layer_outputs: Vec<Vec<f32>>,
+    // Class of the data points added with the mouse.
+    selected_class: usize,
+    // Index of the data point moved with the mouse.
+    dragged: Option<usize>,
     show_graph: bool,
+    // Formula being typed, and why the last one entered could not be parsed.
+    formula_input: Option<String>,
+    formula_error: Option<ParseError>,
+    ui: Ui,
+    show_panel: bool,
+    show_help: bool,
 }
 
 impl GridPoint {
@@ -80,71 +267,302 @@ impl GridPoint {
 fn model(app: &App) -> Model {
     app.new_window()
         .key_pressed(key_pressed)
+        .mouse_pressed(mouse_pressed)
+        .mouse_moved(mouse_moved)
+        .mouse_released(mouse_released)
+        .received_character(received_character)
         .build()
         .unwrap();
 
-    let data = get_data_points(ELEMENTS);
+    let dataset = Dataset::new(DATASET_TYPE, ELEMENTS, DATASET_NOISE, DATASET_CLASSES, snippets::random_seed());
+    let data = dataset.generate();
     let grid_points = data_to_grid_points(&data);
 
-    let window = app.main_window();
-    let win = window.rect();
-
-    let right = win.right() as i32;
-    let top = win.top() as i32;
-
-    let center_to_right = (0..right).step_by(BOUNDARY_STEP);
-    let center_to_top = (0..top).step_by(BOUNDARY_STEP);
-
-    let boundary_predictions = vec![vec![Some(0); center_to_top.len()]; center_to_right.len()];
-
     Model {
         points: grid_points,
         data,
-        network: NeuralNetwork::new(LAYER_CONFIGURATION.to_vec(), &ACTIVATION_TYPE),
+        validation_data: dataset.validation(VALIDATION_ELEMENTS).generate(),
+        dataset,
+        network: create_network(&ACTIVATION_TYPE, &HIDDEN_LAYERS, dataset.num_labels()),
+        hidden_layers: HIDDEN_LAYERS.to_vec(),
+        selected_layer: 0,
         activation_type: ACTIVATION_TYPE,
-        gradient_descent: GradientDescent::new(0.0),
-        boundary_predictions,
+        learn_rate: LEARN_RATE,
+        gradient_descent: GradientDescent::new(FUNCTION_TYPE, vec![0.0; FUNCTION_TYPE.dimensions()], &[OPTIMIZER_TYPE]),
+        optimizer_type: OPTIMIZER_TYPE,
+        region: INPUT_REGION,
+        boundary: None,
         max_chunk_size_factor: 0.5,
         cost: 0.0,
         correct: 0,
         learn: false,
+        learn_error: None,
+        early_stopping: EarlyStopping::new(EARLY_STOPPING_METRIC, EARLY_STOPPING_PATIENCE, EARLY_STOPPING_MIN_DELTA),
+        state: TrainingState::default(),
+        logger: create_logger(),
+        recorder: None,
+        history: History::new(HISTORY_CAPACITY),
+        show_history: true,
+        log_scale: false,
+        show_topology: false,
+        hovered: None,
+        layer_outputs: vec![],
+        selected_class: 0,
+        dragged: None,
         show_graph: false,
+        formula_input: None,
+        formula_error: None,
+        ui: Ui::new(),
+        show_panel: true,
+        show_help: false,
     }
 }
 
 fn new_run(model: &mut Model) {
+    model.dataset.seed = snippets::random_seed();
+    generate_data(model);
+}
+
+// Regenerate the data from the dataset settings, rebuilding the network if the number of classes changed.
+fn generate_data(model: &mut Model) {
     model.learn = false;
 
-    let data = get_data_points(ELEMENTS);
+    let data = model.dataset.generate();
     let grid_points = data_to_grid_points(&data);
 
     model.data = data;
+    model.validation_data = model.dataset.validation(VALIDATION_ELEMENTS).generate();
     model.points = grid_points;
+    model.hovered = None;
+    model.dragged = None;
+
+    let num_labels = model.dataset.num_labels();
+    model.selected_class = model.selected_class.min(num_labels - 1);
+    if model.network.output_shape() != vec![num_labels] {
+        new_network(model);
+    } else {
+        reset_training(model);
+    }
 }
 
 fn new_network(model: &mut Model) {
     model.learn = false;
+    model.learn_error = None;
 
-    model.network = NeuralNetwork::new(LAYER_CONFIGURATION.to_vec(), &model.activation_type);
+    model.network = create_network(&model.activation_type, &model.hidden_layers, model.dataset.num_labels());
+    reset_training(model);
+}
+
+fn reset_training(model: &mut Model) {
+    model.early_stopping.reset();
+    model.state = TrainingState::default();
+    model.history.clear();
+    if let Some(logger) = &mut model.logger {
+        logger.start_run();
+    }
+}
+
+fn create_logger() -> Option<MetricsLogger> {
+    match MetricsLogger::append(Path::new(METRICS_FILE), METRICS_FORMAT) {
+        Ok(logger) => Some(logger),
+        Err(error) => {
+            println!("could not create {}: {}", METRICS_FILE, error);
+            None
+        }
+    }
+}
+
+fn toggle_recording(model: &mut Model) {
+    match &mut model.recorder {
+        Some(recorder) => {
+            println!("recorded {} frames to {}", recorder.finish(), recorder.path.display());
+            model.recorder = None;
+        }
+        None => {
+            let path = recorder::unused_path(Path::new(RECORD_PATH));
+            match Recorder::create(&path, RecordFormat::of_path(&path), RECORD_EVERY, RECORD_FRAME_RATE, RECORD_WIDTH, RECORD_HEIGHT) {
+                Ok(recorder) => {
+                    println!("recording to {}", path.display());
+                    model.recorder = Some(recorder);
+                }
+                Err(error) => println!("could not record to {}: {}", path.display(), error),
+            }
+        }
+    }
+}
+
+fn save_network(model: &Model) {
+    match model.network.save(Path::new(NETWORK_FILE)) {
+        Ok(()) => println!("saved network to {}", NETWORK_FILE),
+        Err(error) => println!("could not save network: {}", error),
+    }
+}
+
+fn load_network(model: &mut Model) {
+    match NeuralNetwork::load(Path::new(NETWORK_FILE)) {
+        Ok(network) if network.input_shape != vec![NUM_INPUTS] || network.output_shape() != vec![model.dataset.num_labels()] => {
+            println!(
+                "could not load network: it maps {:?} to {:?} values, the {} dataset needs {} to {}",
+                network.input_shape, network.output_shape(), model.dataset.dataset_type.name(), NUM_INPUTS, model.dataset.num_labels(),
+            );
+        }
+        Ok(network) => {
+            model.learn = false;
+            model.learn_error = None;
+            // Sizes of the dense layers, except the output layer.
+            let mut sizes: Vec<usize> = network.layers.iter()
+                .filter_map(|layer| layer.weights())
+                .map(|weights| weights[0].len())
+                .collect();
+            sizes.pop();
+            // The activation of the hidden layers, shown and used for new networks like the one chosen with F.
+            if let Some(activation_type) = network.layers.iter().find_map(|layer| layer.activation_type()) {
+                model.activation_type = activation_type;
+            }
+
+            model.selected_layer = 0;
+            model.hidden_layers = sizes;
+            model.network = network;
+            reset_training(model);
+            println!("loaded network from {}", NETWORK_FILE);
+        }
+        Err(error) => println!("could not load network: {}", error),
+    }
+}
+
+fn create_network(activation_type: &ActivationType, hidden_layers: &[usize], num_labels: usize) -> NeuralNetwork {
+    let layer_configuration = [&[NUM_INPUTS][..], hidden_layers, &[num_labels]].concat();
+
+    let mut network = NeuralNetwork::new(layer_configuration, activation_type);
+
+    // Normalization and dropout after the hidden layers only, inserted from the last one so the indices of the dense
+    // layers before it stay the same.
+    for (i, &size) in hidden_layers.iter().enumerate().rev() {
+        if DROPOUT_RATE > 0.0 {
+            network.insert(i + 1, Box::new(Dropout::new(DROPOUT_RATE, snippets::random_seed())));
+        }
+
+        if let Some(normalization) = normalization::create(&NORMALIZATION_TYPE, size) {
+            network.insert(i + 1, normalization);
+        }
+    }
+
+    network.set_regularization(Regularization::new(L1, L2, WEIGHT_DECAY, false));
+    network.gradient_clipping = GradientClipping::new(GRADIENT_CLIP_VALUE, GRADIENT_CLIP_NORM);
+
+    network
 }
 
 fn new_graph(model: &mut Model) {
-    let random = snippets::random_numbers().next().unwrap() as f32 / u64::MAX as f32;
+    let mut random = snippets::random_numbers().map(|random| random as f32 / u64::MAX as f32);
+
+    // Get a start point anywhere in the shown part of the function.
+    let (x_min, x_max, y_min, y_max) = model.gradient_descent.bounds();
+    let x = x_min + random.next().unwrap() * (x_max - x_min);
+    let y = y_min + random.next().unwrap() * (y_max - y_min);
+
+    let mut start = vec![x, y];
+    start.truncate(model.gradient_descent.dimensions());
+    model.gradient_descent.restart(start);
+}
+
+// Switch between the configured optimizers and all of them side by side, from the same start point.
+fn toggle_optimizers(model: &mut Model) {
+    if model.gradient_descent.optimizers.len() < OptimizerType::ALL.len() {
+        model.gradient_descent.set_optimizers(&OptimizerType::ALL);
+    } else {
+        model.gradient_descent.set_optimizers(&[model.optimizer_type]);
+    }
+}
+
+fn select_function(model: &mut Model, function_type: FunctionType) {
+    model.gradient_descent.function_type = function_type;
+    model.gradient_descent.formula = None;
+    model.gradient_descent.learn_rate = model.gradient_descent.default_learn_rate();
+    new_graph(model);
+}
+
+// Start typing a formula, beginning with the current one.
+fn open_formula_input(model: &mut Model) {
+    let source = model.gradient_descent.formula.as_ref().map(|formula| formula.source.clone());
+    model.formula_input = Some(source.unwrap_or_default());
+    model.formula_error = None;
+}
+
+// Use the typed formula for the graph, or the function type again if nothing was typed. A formula that can not be
+// parsed stays in the input with its error.
+fn apply_formula_input(model: &mut Model) {
+    let input = match &model.formula_input {
+        Some(input) => input.trim().to_string(),
+        None => return,
+    };
+
+    if input.is_empty() {
+        model.gradient_descent.formula = None;
+    } else {
+        match Formula::parse(&input) {
+            Ok(formula) => model.gradient_descent.formula = Some(formula),
+            Err(error) => {
+                model.formula_error = Some(error);
+                return;
+            }
+        }
+    }
+
+    model.formula_input = None;
+    model.formula_error = None;
+    model.show_graph = true;
+    model.gradient_descent.learn_rate = model.gradient_descent.default_learn_rate();
+    new_graph(model);
+}
+
+// Run a network learn iteration, stopping the training if it failed.
+fn learn(model: &mut Model, data: &Vec<DataPoint>) -> bool {
+    match model.network.learn(data, model.learn_rate) {
+        Ok(cost) => {
+            model.state.step += 1;
+            model.state.learn_rate = Some(model.learn_rate);
+            model.state.batch_cost = Some(cost);
+            model.state.gradient_norm = Some(model.network.gradient_norm());
+
+            if let Some(recorder) = &mut model.recorder {
+                let history = if model.show_history { Some(&model.history) } else { None };
+                if let Err(error) = recorder.capture(&mut model.network, &model.data, history, &model.region, model.state.step) {
+                    println!("could not record to {}: {}", recorder.path.display(), error);
+                    model.recorder = None;
+                }
+            }
 
-    // Get an x value that is max x% to the left / right from the center.
-    let x_padding: f32 = 2.5;
-    let x: f32 = -x_padding + (random * (2.0 * x_padding));
+            true
+        }
+        Err(error) => {
+            model.learn = false;
+            println!("training stopped, {}", error);
+            model.learn_error = Some(error);
 
-    model.gradient_descent.past_values = vec![];
-    model.gradient_descent.input_value = x;
+            false
+        }
+    }
 }
 
 fn update(app: &App, model: &mut Model, _update: Update) {
-    if model.learn {
+    // Escape cancels typing a formula instead of closing the app.
+    app.set_exit_on_escape(model.formula_input.is_none());
+
+    if model.show_panel {
+        update_panel(app, model);
+    }
+
+    let learning = model.learn;
+    if learning {
+        let data = model.data.clone();
+
         // Create different chunk sizes from 1 to half.
-        for chunk_size in 1..((model.data.len() as f32 * model.max_chunk_size_factor) as usize) {
-            for chunk in model.data.chunks(chunk_size) {
-                model.network.learn(&chunk.to_vec(), model.gradient_descent.learn_rate, model.gradient_descent.h);
+        'learning: for chunk_size in 1..((data.len() as f32 * model.max_chunk_size_factor) as usize) {
+            for chunk in data.chunks(chunk_size) {
+                if !learn(model, &chunk.to_vec()) {
+                    break 'learning;
+                }
             }
         }
     }
@@ -159,21 +577,164 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         }
     }
 
+    if learning {
+        end_epoch(model);
+    }
+
     let window = app.main_window();
     let win = window.rect();
 
-    let right = win.right() as i32;
-    let top = win.top() as i32;
+    // Data point under the mouse.
+    model.hovered = nearest_data_point(model, &win, app.mouse.position())
+        .filter(|&(_, distance)| distance < HOVER_DISTANCE)
+        .map(|(index, _)| index);
+    model.layer_outputs = match model.hovered {
+        Some(index) => model.network.layer_outputs(model.data[index].inputs.to_vec()),
+        None => vec![],
+    };
+
+    let width = (win.w() as usize / BOUNDARY_STEP).max(1);
+    let height = (win.h() as usize / BOUNDARY_STEP).max(1);
+    let image = decision_boundary::render(&mut model.network, &model.region, width, height);
+    model.boundary = Some(boundary_texture(app, image));
+}
+
+// Declare the widgets of the control panel and apply their changes.
+fn update_panel(app: &App, model: &mut Model) {
+    let win = app.main_window().rect();
+    model.ui.begin(
+        win.top_right() + vec2(-PANEL_WIDTH - 10.0, -10.0),
+        PANEL_WIDTH,
+        app.mouse.position(),
+        app.mouse.buttons.left().is_down(),
+    );
+    let ui = &mut model.ui;
+
+    match ui.buttons(&[if model.learn { "pause" } else { "play" }, "step", "reset", "new data"]) {
+        Some(0) => model.learn = !model.learn,
+        Some(1) => {
+            let data = model.data.clone();
+            learn(model, &data);
+        }
+        Some(2) => new_network(model),
+        Some(3) => new_run(model),
+        _ => (),
+    }
 
-    let center_to_right = (0..right).step_by(BOUNDARY_STEP).len();
-    let center_to_top = (0..top).step_by(BOUNDARY_STEP).len();
+    let ui = &mut model.ui;
+    let learn_rate = format!("learn rate: {:.3}", model.learn_rate);
+    ui.slider("learn rate", &learn_rate, &mut model.learn_rate, 0.0, MAX_LEARN_RATE, false);
+    let max_chunk_size_factor = format!("max chunk size factor: {:.2}", model.max_chunk_size_factor);
+    ui.slider("max chunk size factor", &max_chunk_size_factor, &mut model.max_chunk_size_factor, 0.0, 1.0, false);
+    let h = format!("h: {:.6}", model.gradient_descent.h);
+    ui.slider("h", &h, &mut model.gradient_descent.h, H_RANGE.0, H_RANGE.1, true);
+
+    let mut activation = ActivationType::ALL.iter().position(|&activation_type| activation_type == model.activation_type).unwrap();
+    let activation_names: Vec<&str> = ActivationType::ALL.iter().map(ActivationType::name).collect();
+    if ui.dropdown("activation", &activation_names, &mut activation) {
+        model.activation_type = ActivationType::ALL[activation];
+        new_network(model);
+    }
 
-    for x in 0..center_to_right {
-        for y in 0..center_to_top {
-            let inputs = vec![(x * BOUNDARY_STEP) as f32 / right as f32, (y * BOUNDARY_STEP) as f32 / top as f32];
-            model.boundary_predictions[x][y] = model.network.classify(inputs);
+    let ui = &mut model.ui;
+    let mut dataset = DatasetType::ALL.iter().position(|&dataset_type| dataset_type == model.dataset.dataset_type).unwrap();
+    let dataset_names: Vec<&str> = DatasetType::ALL.iter().map(DatasetType::name).collect();
+    let mut changed = ui.dropdown("dataset", &dataset_names, &mut dataset);
+    model.dataset.dataset_type = DatasetType::ALL[dataset];
+
+    let noise = format!("noise: {:.2}", model.dataset.noise);
+    changed |= ui.slider("noise", &noise, &mut model.dataset.noise, 0.0, MAX_DATASET_NOISE, false);
+    let mut size = model.dataset.size as f32;
+    if ui.slider("size", &format!("size: {}", model.dataset.size), &mut size, DATASET_SIZE_STEP as f32, MAX_DATASET_SIZE as f32, false) {
+        changed |= size.round() as usize != model.dataset.size;
+        model.dataset.size = size.round() as usize;
+    }
+    if changed {
+        generate_data(model);
+    }
+
+    let ui = &mut model.ui;
+    let mut function = FunctionType::ALL.iter().position(|&function_type| function_type == model.gradient_descent.function_type).unwrap();
+    let function_names: Vec<&str> = FunctionType::ALL.iter().map(FunctionType::name).collect();
+    if ui.dropdown("function", &function_names, &mut function) {
+        select_function(model, FunctionType::ALL[function]);
+    }
+    let descent_learn_rate = format!("descent learn rate: {:.4}", model.gradient_descent.learn_rate);
+    let (min, max) = DESCENT_LEARN_RATE_RANGE;
+    model.ui.slider("descent learn rate", &descent_learn_rate, &mut model.gradient_descent.learn_rate, min, max, true);
+    if model.ui.button("formula (enter)") {
+        open_formula_input(model);
+    }
+    let mut optimizer = OptimizerType::ALL.iter().position(|&optimizer_type| optimizer_type == model.optimizer_type).unwrap();
+    let optimizer_names: Vec<&str> = OptimizerType::ALL.iter().map(OptimizerType::name).collect();
+    if model.ui.dropdown("optimizer", &optimizer_names, &mut optimizer) {
+        model.optimizer_type = OptimizerType::ALL[optimizer];
+        model.gradient_descent.set_optimizers(&[model.optimizer_type]);
+    }
+    let comparing = model.gradient_descent.optimizers.len() == OptimizerType::ALL.len();
+    if model.ui.button(if comparing { "single optimizer (Z)" } else { "compare optimizers (Z)" }) {
+        toggle_optimizers(model);
+    }
+
+    let layers = format!("layers: {}", architecture_text(model));
+    let ui = &mut model.ui;
+    ui.label(&layers);
+    if ui.button(if model.show_help { "hide help (H)" } else { "help (H)" }) {
+        model.show_help = !model.show_help;
+    }
+
+    model.ui.end();
+}
+
+fn boundary_texture(app: &App, image: BoundaryImage) -> wgpu::Texture {
+    let image = nannou::image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.pixels).unwrap();
+
+    wgpu::Texture::from_image(app, &nannou::image::DynamicImage::ImageRgba8(image))
+}
+
+// Index and distance in pixels of the data point closest to a window position.
+fn nearest_data_point(model: &Model, win: &Rect, position: Vec2) -> Option<(usize, f32)> {
+    model.data.iter()
+        .map(|data_point| to_window(&model.region, win, data_point.inputs[0], data_point.inputs[1]).distance(position))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+}
+
+// Point in the input space at a window position.
+fn to_input(region: &Region, win: &Rect, position: Vec2) -> (f32, f32) {
+    region.input_at((position.x - win.left()) / win.w(), (position.y - win.bottom()) / win.h())
+}
+
+// Window position of a point in the input space.
+fn to_window(region: &Region, win: &Rect, x: f32, y: f32) -> Vec2 {
+    let (u, v) = region.unit_of(x, y);
+
+    vec2(win.left() + u * win.w(), win.bottom() + v * win.h())
+}
+
+// Log the metrics of the frame of learning and check for early stopping.
+fn end_epoch(model: &mut Model) {
+    model.state.epoch += 1;
+    model.state.training_cost = Some(model.cost);
+    model.state.training_accuracy = Some(model.correct as f32 / model.data.len() as f32);
+    model.state.validation_cost = Some(model.network.cost(&model.validation_data));
+    model.state.validation_accuracy = Some(model.network.accuracy(&model.validation_data));
+    model.history.record(&model.state);
+
+    if let Some(logger) = &mut model.logger {
+        if let Err(error) = logger.log(&model.state).and_then(|()| logger.flush()) {
+            println!("could not write {}: {}", METRICS_FILE, error);
+            model.logger = None;
         }
     }
+
+    if model.early_stopping.on_epoch_end(&mut model.network, &model.state) == Control::Stop {
+        model.learn = false;
+        model.early_stopping.restore_best(&mut model.network);
+        println!("early stopping at frame {}, restored best validation {} {:.10} from frame {}",
+            model.state.epoch, EARLY_STOPPING_METRIC.name(), model.early_stopping.best.unwrap_or_default(), model.early_stopping.best_epoch);
+        reset_training(model);
+    }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
@@ -187,29 +748,122 @@ fn view(app: &App, model: &Model, frame: Frame) {
 
     // Draw data stuff.
     draw_data_points(&draw, &win, model);
-    draw_boundries(&draw, &win, model, 2.0);
+    draw_boundary(&draw, &win, model);
     draw_info(&draw, &win, model);
 
+    if model.show_history {
+        draw_history(&draw, &win, model);
+    }
+
+    if model.show_topology {
+        draw_topology(&draw, &win, model);
+    }
+
     if model.show_graph {
         // Draw graph stuff.
-        draw_function_graph(&draw, &win, GradientDescent::function);
-        draw_slope(&draw, model, GradientDescent::function);
+        if model.gradient_descent.dimensions() == 1 {
+            draw_function_graph(&draw, &win, &model.gradient_descent);
+        } else {
+            draw_contours(&draw, &win, &model.gradient_descent);
+        }
+        for optimizer in &model.gradient_descent.optimizers {
+            if model.gradient_descent.dimensions() == 1 {
+                draw_slope(&draw, &model.gradient_descent, optimizer);
+            } else {
+                draw_path(&draw, &win, &model.gradient_descent, optimizer);
+            }
+        }
+        draw_optimizers(&draw, &win, &model.gradient_descent);
+    }
+
+    if model.show_panel {
+        model.ui.draw(&draw, Z_PANEL);
+    }
+
+    if let Some(input) = &model.formula_input {
+        draw_formula_input(&draw, &win, input, model.formula_error.as_ref());
+    }
+
+    if model.show_help {
+        draw_help(&draw, &win);
     }
 
     draw.to_frame(app, &frame).unwrap();
 }
 
+// Layer sizes from the inputs to the outputs, the selected hidden layer in brackets.
+fn architecture_text(model: &Model) -> String {
+    let hidden = model.hidden_layers.iter()
+        .enumerate()
+        .map(|(index, size)| if index == model.selected_layer { format!("[{}]", size) } else { size.to_string() });
+
+    std::iter::once(NUM_INPUTS.to_string())
+        .chain(hidden)
+        .chain(std::iter::once(model.dataset.num_labels().to_string()))
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+fn draw_formula_input(draw: &Draw, win: &Rect, input: &str, error: Option<&ParseError>) {
+    let rect = Rect::from_w_h(500.0, 60.0).mid_bottom_of(*win).shift_y(80.0);
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .z(Z_PANEL)
+        .rgba(0.0, 0.0, 0.0, 0.85);
+
+    let text = match error {
+        Some(error) => format!("f = {}_\n{}", input, error),
+        None => format!("f = {}_\nenter: use, escape: cancel", input),
+    };
+    draw.text(&text)
+        .xy(rect.xy())
+        .wh(rect.pad(10.0).wh())
+        .z(Z_PANEL)
+        .font_size(14)
+        .left_justify()
+        .color(if error.is_some() { ORANGE } else { WHITE });
+}
+
+fn draw_help(draw: &Draw, win: &Rect) {
+    let rect = Rect::from_w_h(460.0, 520.0).middle_of(*win);
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .z(Z_PANEL)
+        .rgba(0.0, 0.0, 0.0, 0.85);
+
+    draw.text(HELP)
+        .xy(rect.xy())
+        .wh(rect.pad(16.0).wh())
+        .z(Z_PANEL)
+        .font_size(13)
+        .left_justify()
+        .align_text_top()
+        .color(WHITE);
+}
+
 fn draw_info(draw: &Draw, win: &Rect, model: &Model) {
-    println!();
     let info_text = format!(
         "cost: {:.10}
 learn rate: {:.5}
 max chunk size factor: {:.1}
 h: {:.10}
-correct: {}/{}",
-        model.cost, model.gradient_descent.learn_rate, model.max_chunk_size_factor, model.gradient_descent.h, model.correct, model.data.len());
-
-    println!("{}", info_text);
+correct: {}/{}
+class: {}
+dataset: {}, noise {:.2}, classes {}
+layers: {}, activation {}",
+        model.cost, model.learn_rate, model.max_chunk_size_factor, model.gradient_descent.h, model.correct, model.data.len(),
+        model.selected_class, model.dataset.dataset_type.name(), model.dataset.noise, model.dataset.num_labels(),
+        architecture_text(model), model.activation_type.name());
+    let info_text = match &model.recorder {
+        Some(recorder) => format!("{}\nrecording, {} frames", info_text, recorder.frames),
+        None => info_text,
+    };
+    let info_text = match &model.learn_error {
+        Some(error) => format!("{}\ntraining stopped, {}", info_text, error),
+        None => info_text,
+    };
 
     let pad = 6.0;
     draw.text(&info_text)
@@ -221,17 +875,184 @@ correct: {}/{}",
         .color(WHITE);
 }
 
-fn graph_derivative(x: f32) -> f32 {
-    // derivative of: 0.2 * x.powf(4.0) + 0.1 * x.powf(3.0) - x.powf(2.0) + 2.0
-    0.8 * x.powf(3.0) + 0.3 * x.powf(2.0) - 2.0 * x
+// Draw the nodes of every layer with the weights between them, in the top left quarter.
+// The nodes are shaded by their outputs for the hovered data point.
+fn draw_topology(draw: &Draw, win: &Rect, model: &Model) {
+    let rect = Rect::from_corners(win.mid_left(), win.mid_top()).pad(20.0);
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .z(Z_UI)
+        .rgba(0.0, 0.0, 0.0, 0.6);
+
+    let hint = if model.hovered.is_some() { "outputs of the hovered data point" } else { "hover a data point to see its outputs" };
+    draw.text(hint)
+        .xy(rect.mid_top() + vec2(0.0, -10.0))
+        .w(rect.w())
+        .z(Z_UI)
+        .color(WHITE);
+
+    // Number of nodes of the inputs and of every layer.
+    let network = &model.network;
+    let mut shape = network.input_shape.clone();
+    let mut sizes: Vec<usize> = vec![shape.iter().product()];
+    for layer in &network.layers {
+        shape = layer.output_shape(&shape);
+        sizes.push(shape.iter().product());
+    }
+
+    let area = rect.pad(30.0);
+    let column_step = area.w() / (sizes.len() - 1).max(1) as f32;
+    let node_position = |column: usize, node: usize| vec2(
+        area.left() + column as f32 * column_step,
+        area.top() - (node as f32 + 0.5) / sizes[column] as f32 * area.h(),
+    );
+
+    // Edges, colored by the sign and thickened by the magnitude of the weights.
+    let max_weight = network.layers.iter()
+        .filter_map(|layer| layer.weights())
+        .flatten()
+        .flatten()
+        .fold(0.0f32, |max, weight| max.max(weight.abs()));
+
+    for (column, layer) in network.layers.iter().enumerate() {
+        match layer.weights() {
+            Some(weights) => {
+                for (node_in, weights) in weights.iter().enumerate() {
+                    for (node_out, &weight) in weights.iter().enumerate() {
+                        let magnitude = weight.abs() / max_weight.max(f32::EPSILON);
+                        let color = if weight > 0.0 { COLOR_POSITIVE } else { COLOR_NEGATIVE };
+
+                        draw.line()
+                            .start(node_position(column, node_in))
+                            .end(node_position(column + 1, node_out))
+                            .z(Z_UI)
+                            .weight(0.5 + 4.0 * magnitude)
+                            .color(srgba(color.red, color.green, color.blue, (55.0 + 200.0 * magnitude) as u8));
+                    }
+                }
+            }
+            // Element-wise layers like dropout or normalization.
+            None if sizes[column] == sizes[column + 1] => {
+                for node in 0..sizes[column] {
+                    draw.line()
+                        .start(node_position(column, node))
+                        .end(node_position(column + 1, node))
+                        .z(Z_UI)
+                        .weight(1.0)
+                        .color(DARKGRAY);
+                }
+            }
+            None => (),
+        }
+    }
+
+    // Nodes, shaded by their outputs relative to the largest output of the layer.
+    for (column, &size) in sizes.iter().enumerate() {
+        let outputs = model.layer_outputs.get(column);
+        let max_output = outputs.map_or(0.0, |outputs| outputs.iter().fold(0.0f32, |max, output| max.max(output.abs())));
+        let radius = (area.h() / size as f32 / 2.5).min(14.0);
+
+        for node in 0..size {
+            let position = node_position(column, node);
+            let output = outputs.and_then(|outputs| outputs.get(node));
+            let shade = output.map_or(0.3, |output| 0.15 + 0.85 * output.abs() / max_output.max(f32::EPSILON));
+
+            draw.ellipse()
+                .xy(position)
+                .z(Z_UI)
+                .radius(radius)
+                .color(gray(shade));
+
+            if let Some(output) = output {
+                draw.text(&format!("{:.2}", output))
+                    .xy(position + vec2(0.0, radius + 8.0))
+                    .z(Z_UI)
+                    .color(WHITE);
+            }
+        }
+    }
 }
 
-fn draw_slope(draw: &Draw, model: &Model, graph_function: fn(f32) -> f32) {
-    let past_values = &model.gradient_descent.past_values;
-    let x = model.gradient_descent.input_value;
-    let y = graph_function(x);
+// Plot the costs and accuracies of the training and validation data over the steps, in the bottom right quarter.
+fn draw_history(draw: &Draw, win: &Rect, model: &Model) {
+    let pad = 20.0;
+    let quarter = Rect::from_corners(win.mid_bottom(), win.mid_right()).pad(pad);
+    let cost_rect = Rect::from_corners(quarter.top_left(), quarter.mid_right()).pad_bottom(pad / 2.0);
+    let accuracy_rect = Rect::from_corners(quarter.mid_left(), quarter.bottom_right()).pad_top(pad / 2.0);
+
+    let history = &model.history;
+    let cost_title = if model.log_scale { "cost (log10)" } else { "cost" };
+    draw_plot(draw, &cost_rect, history, cost_title, &[
+        (history.series(|state| state.training_cost, model.log_scale), COLOR_TRAINING),
+        (history.series(|state| state.validation_cost, model.log_scale), COLOR_VALIDATION),
+    ]);
+    draw_plot(draw, &accuracy_rect, history, "accuracy", &[
+        (history.series(|state| state.training_accuracy, false), COLOR_TRAINING),
+        (history.series(|state| state.validation_accuracy, false), COLOR_VALIDATION),
+    ]);
+}
 
-    let slope = graph_derivative(x);
+// Draw series of values by step into a rectangle, with the axes scaled to fit all of them.
+fn draw_plot(draw: &Draw, rect: &Rect, history: &History, title: &str, series: &[Series<Srgb<u8>>]) {
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .z(Z_UI)
+        .rgba(0.0, 0.0, 0.0, 0.6);
+
+    let values: Vec<Vec<(usize, f32)>> = series.iter().map(|(values, _)| values.clone()).collect();
+    let (first_step, last_step) = match history.steps() {
+        Some(steps) => steps,
+        None => return,
+    };
+    let (min, max) = match history::value_range(&values) {
+        Some(range) => range,
+        None => return,
+    };
+
+    // Title, legend and axis labels.
+    let label_w = 160.0;
+    let labels = [
+        (format!("{}, steps {} to {}", title, first_step, last_step), rect.top_left() + vec2(label_w / 2.0 + 4.0, -10.0), WHITE),
+        ("training".to_string(), rect.top_right() + vec2(-label_w / 2.0 - 4.0, -10.0), COLOR_TRAINING),
+        ("validation".to_string(), rect.top_right() + vec2(-label_w / 2.0 - 4.0, -24.0), COLOR_VALIDATION),
+        (format!("{:.4}", max), rect.top_left() + vec2(label_w / 2.0 + 4.0, -24.0), GRAY),
+        (format!("{:.4}", min), rect.bottom_left() + vec2(label_w / 2.0 + 4.0, 10.0), GRAY),
+    ];
+    for (label, xy, color) in &labels {
+        draw.text(label)
+            .xy(*xy)
+            .w(label_w)
+            .z(Z_UI)
+            .left_justify()
+            .color(*color);
+    }
+
+    let step_range = (last_step - first_step).max(1) as f32;
+    for (values, color) in series {
+        let points: Vec<Point3> = values.iter()
+            .map(|&(step, value)| pt3(
+                rect.left() + (step - first_step) as f32 / step_range * rect.w(),
+                rect.bottom() + (value - min) / (max - min) * rect.h(),
+                Z_UI,
+            ))
+            .collect();
+
+        draw.polyline()
+            .weight(1.5)
+            .points(points)
+            .color(*color);
+    }
+}
+
+fn draw_slope(draw: &Draw, gradient_descent: &GradientDescent, optimizer: &Optimizer) {
+    let color = optimizer_color(optimizer.optimizer_type);
+    let past_values = &optimizer.past_values;
+    let x = optimizer.position[0];
+    let y = gradient_descent.function(&[x]);
+
+    let slope = gradient_descent.slope(&[x])[0];
 
     let weight_x = 2.0;
 
@@ -239,17 +1060,17 @@ fn draw_slope(draw: &Draw, model: &Model, graph_function: fn(f32) -> f32) {
     draw.ellipse()
         .x(x * GRAPH_SCALING)
         .z(Z_UI)
-        .color(WHITE)
+        .color(color)
         .wh(vec2(weight_x, weight_x));
 
     let weight_point = 8.0;
     // Draw past values.
-    for past_x in past_values {
-        let point = Vec2::new(past_x * GRAPH_SCALING, graph_function(*past_x) * GRAPH_SCALING);
+    for past_value in past_values {
+        let point = Vec2::new(past_value[0] * GRAPH_SCALING, gradient_descent.function(past_value) * GRAPH_SCALING);
         draw.ellipse()
             .xy(point)
             .z(Z_GRAPH)
-            .color(DARKGRAY)
+            .color(trail_color(color))
             .wh(vec2(weight_point, weight_point));
     }
     // Draw point.
@@ -257,7 +1078,7 @@ fn draw_slope(draw: &Draw, model: &Model, graph_function: fn(f32) -> f32) {
     draw.ellipse()
         .xy(point)
         .z(Z_UI)
-        .color(WHITE)
+        .color(color)
         .wh(vec2(weight_point, weight_point));
 
     // Draw point text.
@@ -265,7 +1086,7 @@ fn draw_slope(draw: &Draw, model: &Model, graph_function: fn(f32) -> f32) {
     draw.text(&slope_text)
         .xy(point + vec2(0.0, 20.0))
         .z(Z_UI)
-        .color(WHITE);
+        .color(color);
 
     // Visualize the slope.
     let slope_direction = Vec2::new(1.0, slope).normalize();
@@ -280,14 +1101,122 @@ fn draw_slope(draw: &Draw, model: &Model, graph_function: fn(f32) -> f32) {
         .weight(weight_slope);
 }
 
-fn draw_function_graph(draw: &Draw, win: &Rect, graph_function: fn(f32) -> f32) {
+// Window position of a point of a two dimensional function, with its bounds fitted into the window.
+fn to_graph(win: &Rect, bounds: (f32, f32, f32, f32), x: f32, y: f32) -> Vec2 {
+    let (x_min, x_max, y_min, y_max) = bounds;
+    let scale = 0.9 * (win.w() / (x_max - x_min)).min(win.h() / (y_max - y_min));
+
+    win.xy() + vec2(x - (x_min + x_max) / 2.0, y - (y_min + y_max) / 2.0) * scale
+}
+
+fn draw_contours(draw: &Draw, win: &Rect, gradient_descent: &GradientDescent) {
+    let bounds = gradient_descent.bounds();
+    let grid = Grid::sample(|x, y| gradient_descent.function(&[x, y]), bounds, CONTOUR_RESOLUTION, CONTOUR_RESOLUTION);
+    let (min, max) = match grid.range() {
+        Some(range) => range,
+        None => return,
+    };
+
+    for level in contour::levels(min, max, CONTOUR_LEVELS) {
+        for ((x0, y0), (x1, y1)) in grid.contour(level) {
+            draw.line()
+                .start(to_graph(win, bounds, x0, y0))
+                .end(to_graph(win, bounds, x1, y1))
+                .z(Z_GRAPH)
+                .color(STEELBLUE)
+                .weight(1.0);
+        }
+    }
+}
+
+// Draw the steps taken on a two dimensional function, like `draw_slope`, with the gradient at the current point.
+fn draw_path(draw: &Draw, win: &Rect, gradient_descent: &GradientDescent, optimizer: &Optimizer) {
+    let color = optimizer_color(optimizer.optimizer_type);
+    let bounds = gradient_descent.bounds();
+    let window_position = |point: &[f32]| to_graph(win, bounds, point[0], point[1]);
+    let point = window_position(&optimizer.position);
+
+    let weight_point = 8.0;
+    let trail: Vec<Point3> = optimizer.past_values.iter()
+        .chain(std::iter::once(&optimizer.position))
+        .map(|value| window_position(value).extend(Z_GRAPH))
+        .collect();
+    draw.polyline()
+        .weight(1.0)
+        .points(trail)
+        .color(trail_color(color));
+    for past_value in &optimizer.past_values {
+        draw.ellipse()
+            .xy(window_position(past_value))
+            .z(Z_GRAPH)
+            .color(trail_color(color))
+            .wh(vec2(weight_point, weight_point));
+    }
+    draw.ellipse()
+        .xy(point)
+        .z(Z_UI)
+        .color(color)
+        .wh(vec2(weight_point, weight_point));
+
+    // Value and direction of the gradient.
+    let slope = gradient_descent.slope(&optimizer.position);
+    let slope = vec2(slope[0], slope[1]);
+    draw.text(&format!("{:.2}", gradient_descent.function(&optimizer.position)))
+        .xy(point + vec2(0.0, 20.0))
+        .z(Z_UI)
+        .color(color);
+    draw.arrow()
+        .start(point)
+        .end(point + slope.normalize_or_zero() * 40.0)
+        .z(Z_GRAPH)
+        .color(RED)
+        .weight(2.0);
+}
+
+fn optimizer_color(optimizer_type: OptimizerType) -> Srgb<u8> {
+    OPTIMIZER_COLORS[OptimizerType::ALL.iter().position(|&other| other == optimizer_type).unwrap()]
+}
+
+// Darker color of the points an optimizer stepped from.
+fn trail_color(color: Srgb<u8>) -> Srgb<u8> {
+    srgb(color.red / 2, color.green / 2, color.blue / 2)
+}
+
+// List the function and the optimizers at the top of the window, with their steps and the value of the function where they are.
+fn draw_optimizers(draw: &Draw, win: &Rect, gradient_descent: &GradientDescent) {
+    let line_height = 16.0;
+    draw.text(&format!("f = {}, learn rate {}", gradient_descent.name(), gradient_descent.learn_rate))
+        .x_y(win.x(), win.top() - line_height)
+        .w(300.0)
+        .z(Z_UI)
+        .left_justify()
+        .color(STEELBLUE);
+    for (index, optimizer) in gradient_descent.optimizers.iter().enumerate() {
+        let text = if optimizer.diverged {
+            format!("{}: diverged after {} steps", optimizer.optimizer_type.name(), optimizer.steps())
+        } else {
+            format!(
+                "{}: {} steps, value {:.4}",
+                optimizer.optimizer_type.name(), optimizer.steps(), gradient_descent.function(&optimizer.position),
+            )
+        };
+        draw.text(&text)
+            .x_y(win.x(), win.top() - line_height * (index as f32 + 2.0))
+            .w(300.0)
+            .z(Z_UI)
+            .left_justify()
+            .color(optimizer_color(optimizer.optimizer_type));
+    }
+}
+
+fn draw_function_graph(draw: &Draw, win: &Rect, gradient_descent: &GradientDescent) {
     let left = win.left() as i32;
     let right = win.right() as i32;
 
     // Create vector with points for every pixel from left to right (x axis).
     let mut points: Vec<Point3> = Vec::with_capacity((left..right).count());
     for x in left..right {
-        let y = graph_function(x as f32 / GRAPH_SCALING) * GRAPH_SCALING;
+        let y = gradient_descent.function(&[x as f32 / GRAPH_SCALING]) * GRAPH_SCALING;
         points.push(pt3(x as f32, y, Z_GRAPH));
     }
 
@@ -299,12 +1228,20 @@ fn draw_function_graph(draw: &Draw, win: &Rect, graph_function: fn(f32) -> f32)
 }
 
 fn draw_data_points(draw: &Draw, win: &Rect, model: &Model) {
+    // Highlight the hovered data point.
+    if let Some(point) = model.hovered.map(|index| &model.points[index]) {
+        draw.ellipse()
+            .wh(vec2(16.0, 16.0))
+            .xy(to_window(&model.region, win, point.x, point.y))
+            .z(point.z - 0.5)
+            .color(WHITE);
+    }
+
     // Draw grid points.
     for point in &model.points {
         draw.ellipse()
             .wh(vec2(10.0, 10.0))
-            .x(point.x * win.right())
-            .y(point.y * win.top())
+            .xy(to_window(&model.region, win, point.x, point.y))
             .z(point.z)
             .color(point.color);
     }
@@ -327,28 +1264,19 @@ fn draw_crosshair(draw: &Draw, win: &Rect) {
     }
 }
 
-fn draw_boundries(draw: &Draw, win: &Rect, model: &Model, weight: f32) {
-    let right = win.right() as i32;
-    let top = win.top() as i32;
-
-    let center_to_right = (0..right).step_by(BOUNDARY_STEP).len();
-    let center_to_top = (0..top).step_by(BOUNDARY_STEP).len();
-
-    for x in 0..center_to_right {
-        for y in 0..center_to_top {
-            let predicted_class = model.boundary_predictions[x][y];
+// Draw the classes predicted over the whole window, stretched from the texture.
+fn draw_boundary(draw: &Draw, win: &Rect, model: &Model) {
+    if let Some(texture) = &model.boundary {
+        draw.texture(texture)
+            .wh(win.wh())
+            .z(Z_BOUNDRY);
+    }
+}
 
-            let pixel = draw.ellipse()
-                .xyz(vec3((x * BOUNDARY_STEP) as f32, (y * BOUNDARY_STEP) as f32, Z_BOUNDRY))
-                .wh(vec2(weight, weight));
+fn class_color(class: usize) -> Srgb<u8> {
+    let [red, green, blue] = decision_boundary::class_color(class);
 
-            if predicted_class == Some(0) {
-                pixel.color(COLOR_SAFE);
-            } else if predicted_class == Some(1) {
-                pixel.color(COLOR_UNSAFE);
-            }
-        }
-    }
+    srgb(red, green, blue)
 }
 
 fn data_to_grid_points(data: &Vec<DataPoint>) -> Vec<GridPoint> {
@@ -359,55 +1287,178 @@ fn data_to_grid_points(data: &Vec<DataPoint>) -> Vec<GridPoint> {
             data_point.inputs[0],
             data_point.inputs[1],
             Z_POINTS,
-            if data_point.label == 1 { COLOR_UNSAFE } else { COLOR_SAFE },
+            class_color(data_point.label),
         ));
     }
 
     grid_points
 }
 
-fn get_data_points(elements: usize) -> Vec<DataPoint> {
-    let mut data: Vec<DataPoint> = Vec::new();
+// Left click drags the data point under the mouse, or adds one of the selected class. Right click removes the nearest one.
+fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    let win = app.main_window().rect();
+    let position = app.mouse.position();
+    if model.show_panel && model.ui.contains(position) {
+        return;
+    }
 
-    for _ in 0..elements {
-        // Get random float values between 0 and 1 for x and y.
-        let x = snippets::random_numbers().next().unwrap() as f32 / u64::MAX as f32;
-        let y = snippets::random_numbers().next().unwrap() as f32 / u64::MAX as f32;
+    let nearest = nearest_data_point(model, &win, position);
 
-        // Apply padding to the axis values.
-        let min = 0.0 + VALUE_PADDING;
-        let max = 1.0 - VALUE_PADDING;
-        let spot_size = min + (x * (max - min));
-        let spike_length = min + (y * (max - min));
+    match button {
+        MouseButton::Left => match nearest.filter(|&(_, distance)| distance < HOVER_DISTANCE) {
+            Some((index, _)) => model.dragged = Some(index),
+            None => {
+                let (x, y) = to_input(&model.region, &win, position);
+                let num_labels = model.network.output_shape().iter().product();
+                model.data.push(DataPoint::new(vec![x, y], model.selected_class, num_labels));
+                model.dragged = Some(model.data.len() - 1);
+            }
+        },
+        // The last data point stays, the cost and accuracy are averages over the data.
+        MouseButton::Right => {
+            if let Some((index, _)) = nearest.filter(|_| model.data.len() > 1) {
+                model.data.remove(index);
+                model.hovered = None;
+                model.dragged = None;
+            }
+        }
+        _ => (),
+    }
 
-        // Set poisenous flag based on the class threshold.
-        let poisenous = spot_size.pow(0.8) * spike_length.pow(0.8) > CLASS_THRESHOLD;
+    model.points = data_to_grid_points(&model.data);
+}
 
-        data.push(DataPoint::new(
-            vec![spot_size, spike_length],
-            if poisenous { 1 } else { 0 },
-            2,
-        ));
+fn mouse_moved(app: &App, model: &mut Model, position: Point2) {
+    if let Some(index) = model.dragged {
+        let (x, y) = to_input(&model.region, &app.main_window().rect(), position);
+        model.data[index].inputs = vec![x, y];
+        model.points = data_to_grid_points(&model.data);
+    }
+}
+
+fn mouse_released(_app: &App, model: &mut Model, button: MouseButton) {
+    if button == MouseButton::Left {
+        model.dragged = None;
     }
+}
 
-    data
+// Select the class of the data points added with the mouse, if the network has an output for it.
+fn select_class(model: &mut Model, class: usize) {
+    if class < model.network.output_shape().iter().product::<usize>() {
+        model.selected_class = class;
+    }
+}
+
+// Change the hidden layers and rebuild the network from them.
+fn change_architecture(model: &mut Model, change: fn(&mut Model)) {
+    change(model);
+    model.selected_layer = model.selected_layer.min(model.hidden_layers.len().saturating_sub(1));
+    new_network(model);
+}
+
+fn received_character(_app: &App, model: &mut Model, character: char) {
+    if let Some(input) = &mut model.formula_input {
+        if !character.is_control() {
+            input.push(character);
+        }
+    }
 }
 
 fn key_pressed(_app: &App, model: &mut Model, key: Key) {
     // println!("key pressed: {:?}", key);
 
+    // Keys edit the formula while one is typed.
+    if model.formula_input.is_some() {
+        match key {
+            Key::Return => apply_formula_input(model),
+            Key::Escape => model.formula_input = None,
+            Key::Back => {
+                model.formula_input.as_mut().unwrap().pop();
+            }
+            _ => (),
+        }
+        return;
+    }
+
     match key {
         // New
         Key::N => new_run(model),
+        // Select the next hidden layer.
+        Key::Tab => model.selected_layer = (model.selected_layer + 1) % model.hidden_layers.len().max(1),
+        // Add a hidden layer after the selected one, of the same size.
+        Key::A => change_architecture(model, |model| {
+            if model.hidden_layers.len() < MAX_HIDDEN_LAYERS {
+                let (index, size) = match model.hidden_layers.get(model.selected_layer) {
+                    Some(&size) => (model.selected_layer + 1, size),
+                    None => (0, HIDDEN_LAYERS[0]),
+                };
+                model.hidden_layers.insert(index, size);
+                model.selected_layer = index;
+            }
+        }),
+        // Remove the selected hidden layer.
+        Key::X => change_architecture(model, |model| {
+            if model.selected_layer < model.hidden_layers.len() {
+                model.hidden_layers.remove(model.selected_layer);
+            }
+        }),
+        // Neurons of the selected hidden layer.
+        Key::E => change_architecture(model, |model| {
+            if let Some(size) = model.hidden_layers.get_mut(model.selected_layer) {
+                *size = (*size + 1).min(MAX_NEURONS);
+            }
+        }),
+        Key::Q => change_architecture(model, |model| {
+            if let Some(size) = model.hidden_layers.get_mut(model.selected_layer) {
+                *size = (*size - 1).max(1);
+            }
+        }),
+        // Next activation function.
+        Key::F => change_architecture(model, |model| model.activation_type = model.activation_type.next()),
+        // Next dataset.
+        Key::D => {
+            model.dataset.dataset_type = model.dataset.dataset_type.next();
+            generate_data(model);
+        }
+        // Dataset noise.
+        Key::RBracket => {
+            model.dataset.noise += DATASET_NOISE_STEP;
+            generate_data(model);
+        }
+        Key::LBracket => {
+            model.dataset.noise = (model.dataset.noise - DATASET_NOISE_STEP).max(0.0);
+            generate_data(model);
+        }
+        // Dataset size.
+        Key::Period => {
+            model.dataset.size += DATASET_SIZE_STEP;
+            generate_data(model);
+        }
+        Key::Comma => {
+            model.dataset.size = model.dataset.size.saturating_sub(DATASET_SIZE_STEP).max(DATASET_SIZE_STEP);
+            generate_data(model);
+        }
+        // Number of classes of the datasets that support it.
+        Key::C => {
+            model.dataset.classes = if model.dataset.classes < DATASET_MAX_CLASSES { model.dataset.classes + 1 } else { 2 };
+            generate_data(model);
+        }
         // New network.
         Key::S => new_network(model),
         // Network learn iteration.
-        Key::I => model.network.learn(&model.data, model.gradient_descent.learn_rate, model.gradient_descent.h),
+        Key::I => {
+            let data = model.data.clone();
+            learn(model, &data);
+        }
+        // Save network.
+        Key::W => save_network(model),
+        // Load network.
+        Key::O => load_network(model),
         // Toggle learning.
         Key::Space => model.learn = !model.learn,
         // Learn rate.
-        Key::Up => model.gradient_descent.learn_rate += GradientDescent::LEARN_RATE_STEP,
-        Key::Down => model.gradient_descent.learn_rate -= GradientDescent::LEARN_RATE_STEP,
+        Key::Up => model.learn_rate += GradientDescent::LEARN_RATE_STEP,
+        Key::Down => model.learn_rate -= GradientDescent::LEARN_RATE_STEP,
         // Max chunk size factor.
         Key::K => model.max_chunk_size_factor += MAX_CHUNK_SIZE_FACTOR_STEP,
         Key::J => model.max_chunk_size_factor -= MAX_CHUNK_SIZE_FACTOR_STEP,
@@ -416,17 +1467,45 @@ fn key_pressed(_app: &App, model: &mut Model, key: Key) {
         Key::Left => model.gradient_descent.h *= GradientDescent::H_FACTOR,
         // Toggle graph.
         Key::G => model.show_graph = !model.show_graph,
+        // Toggle control panel and help.
+        Key::M => model.show_panel = !model.show_panel,
+        Key::H => model.show_help = !model.show_help,
+        // Start or stop recording the training.
+        Key::V => toggle_recording(model),
+        // Toggle plot of the training history.
+        Key::P => model.show_history = !model.show_history,
+        // Toggle log scale of the cost plot.
+        Key::Y => model.log_scale = !model.log_scale,
+        // Toggle view of the network topology.
+        Key::T => model.show_topology = !model.show_topology,
+        // Class of the data points added with the mouse.
+        Key::Key0 => select_class(model, 0),
+        Key::Key1 => select_class(model, 1),
+        Key::Key2 => select_class(model, 2),
+        Key::Key3 => select_class(model, 3),
+        Key::Key4 => select_class(model, 4),
+        Key::Key5 => select_class(model, 5),
+        Key::Key6 => select_class(model, 6),
+        Key::Key7 => select_class(model, 7),
+        Key::Key8 => select_class(model, 8),
+        Key::Key9 => select_class(model, 9),
+        // Zoom the input region in and out.
+        Key::Equals => model.region = model.region.zoom(1.0 / ZOOM_FACTOR),
+        Key::Minus => model.region = model.region.zoom(ZOOM_FACTOR),
         // New graph.
         Key::R => new_graph(model),
+        // Next function of the graph.
+        Key::B => select_function(model, model.gradient_descent.function_type.next()),
         // Graph learn iteration.
         Key::L => model.gradient_descent.learn(),
+        // Run all optimizers side by side.
+        Key::Z => toggle_optimizers(model),
+        // Type a formula for the graph.
+        Key::Return => open_formula_input(model),
         _ => (),
     }
 
-    model.gradient_descent.learn_rate = model.gradient_descent.learn_rate.clamp(0.0, 100.0);
+    model.learn_rate = model.learn_rate.clamp(0.0, MAX_LEARN_RATE);
     model.max_chunk_size_factor = model.max_chunk_size_factor.clamp(0.0, 1.0);
     model.gradient_descent.h = model.gradient_descent.h.clamp(0.0, 1.0);
-
-    println!("learn rate: {:.5}", model.gradient_descent.learn_rate);
-    println!("h: {:.10}", model.gradient_descent.h);
 }
\ No newline at end of file
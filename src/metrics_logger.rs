@@ -0,0 +1,293 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::callback::{Callback, Control, TrainingState};
+use crate::float::Float;
+use crate::neural_network::NeuralNetwork;
+use crate::serialization;
+
+const COLUMNS: [&str; 11] = [
+    "run",
+    "step",
+    "epoch",
+    "learn_rate",
+    "batch_cost",
+    "gradient_norm",
+    "training_cost",
+    "training_accuracy",
+    "validation_cost",
+    "validation_accuracy",
+    "wall_time",
+];
+// Bytes read from the end of a log to find the last run, more than a record takes.
+const TAIL_LENGTH: u64 = 4096;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LogFormat {
+    // Header line, then comma separated values. Missing values are left empty, values that are not finite are NaN,
+    // inf or -inf.
+    CSV,
+    // One JSON object per line. Missing values are null, values that are not finite are the strings "NaN", "Infinity"
+    // or "-Infinity", since JSON has no numbers for them.
+    JSONL,
+}
+
+// Writes the training metrics as one record per epoch (or per batch) to a file. Every run of training has its own id,
+// so the runs appended to the same file can be told apart.
+pub struct MetricsLogger<W: Write = BufWriter<File>> {
+    pub format: LogFormat,
+    // Also write a record at the end of every batch.
+    pub log_batches: bool,
+    // First error writing the log, which stops the training.
+    pub error: Option<io::Error>,
+    // Milliseconds since the Unix epoch at the start of the current run.
+    pub run: u128,
+    writer: W,
+    start: Instant,
+}
+
+impl MetricsLogger {
+    // Append to the file, writing the header only if it is new. A file in the other format or, for CSV, with other
+    // columns is not appended to. The run ids continue after the last run in the file.
+    pub fn append(path: &Path, format: LogFormat) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut first_line = String::new();
+        BufReader::new(&file).read_line(&mut first_line)?;
+        let first_line = first_line.trim_end();
+        let matches = match format {
+            LogFormat::CSV => first_line == COLUMNS.join(","),
+            LogFormat::JSONL => first_line.starts_with("{\"run\":"),
+        };
+        if !first_line.is_empty() && !matches {
+            return Err(serialization::invalid_data(format!("{} is not a {:?} log with the columns {}", path.display(), format, COLUMNS.join(","))));
+        }
+
+        // Only the end of the file is read for the last run, logs grow long.
+        let length = file.metadata()?.len();
+        file.seek(SeekFrom::Start(length.saturating_sub(TAIL_LENGTH)))?;
+        let mut tail = vec![];
+        file.read_to_end(&mut tail)?;
+        let last_run = String::from_utf8_lossy(&tail).lines().last().and_then(Self::run_of);
+
+        let mut logger = Self::with_header(BufWriter::new(file), format, first_line.is_empty())?;
+        if let Some(last_run) = last_run {
+            logger.run = logger.run.max(last_run + 1);
+        }
+
+        Ok(logger)
+    }
+
+    // Run id at the start of a CSV or JSON line.
+    fn run_of(line: &str) -> Option<u128> {
+        let line = line.strip_prefix("{\"run\":").unwrap_or(line);
+        let end = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+
+        line[..end].parse().ok()
+    }
+}
+
+impl<W: Write> MetricsLogger<W> {
+    // Log to any writer, starting with the header.
+    #[allow(dead_code)]
+    pub fn new(writer: W, format: LogFormat) -> io::Result<Self> {
+        Self::with_header(writer, format, true)
+    }
+
+    fn with_header(mut writer: W, format: LogFormat, header: bool) -> io::Result<Self> {
+        if format == LogFormat::CSV && header {
+            writeln!(writer, "{}", COLUMNS.join(","))?;
+        }
+
+        let mut logger = MetricsLogger {
+            format,
+            log_batches: false,
+            error: None,
+            run: 0,
+            writer,
+            start: Instant::now(),
+        };
+        logger.start_run();
+
+        Ok(logger)
+    }
+
+    // Start a new run with its own id and wall time, e.g. after the network was reset.
+    pub fn start_run(&mut self) {
+        let run = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+        // Runs started within the same millisecond still get different ids.
+        self.run = run.max(self.run + 1);
+        self.start = Instant::now();
+    }
+
+    // Write a record of the state. The wall time is in seconds since the run started.
+    pub fn log<T: Float>(&mut self, state: &TrainingState<T>) -> io::Result<()> {
+        let format = self.format;
+        let optional = |value: Option<T>| value.map(|value| Self::format_value(format, value));
+        let values = [
+            Some(self.run.to_string()),
+            Some(state.step.to_string()),
+            Some(state.epoch.to_string()),
+            optional(state.learn_rate),
+            optional(state.batch_cost),
+            optional(state.gradient_norm),
+            optional(state.training_cost),
+            optional(state.training_accuracy),
+            optional(state.validation_cost),
+            optional(state.validation_accuracy),
+            Some(self.start.elapsed().as_secs_f64().to_string()),
+        ];
+
+        match self.format {
+            LogFormat::CSV => {
+                let values: Vec<String> = values.into_iter().map(Option::unwrap_or_default).collect();
+                writeln!(self.writer, "{}", values.join(","))
+            }
+            LogFormat::JSONL => {
+                let fields: Vec<String> = COLUMNS.iter()
+                    .zip(values)
+                    .map(|(column, value)| format!("\"{}\":{}", column, value.unwrap_or_else(|| "null".to_string())))
+                    .collect();
+                writeln!(self.writer, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn format_value<T: Float>(format: LogFormat, value: T) -> String {
+        if value.is_finite() {
+            return value.to_string();
+        }
+
+        let text = match (format, value.is_nan(), value > T::zero()) {
+            (LogFormat::CSV, true, _) => "NaN",
+            (LogFormat::CSV, false, true) => "inf",
+            (LogFormat::CSV, false, false) => "-inf",
+            (LogFormat::JSONL, true, _) => "\"NaN\"",
+            (LogFormat::JSONL, false, true) => "\"Infinity\"",
+            (LogFormat::JSONL, false, false) => "\"-Infinity\"",
+        };
+
+        text.to_string()
+    }
+
+    fn log_or_stop<T: Float>(&mut self, state: &TrainingState<T>) -> Control {
+        match self.log(state) {
+            Ok(()) => Control::Continue,
+            Err(error) => {
+                self.error = Some(error);
+                Control::Stop
+            }
+        }
+    }
+}
+
+impl<T: Float, W: Write> Callback<T> for MetricsLogger<W> {
+    fn on_train_start(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) -> Control {
+        self.start_run();
+
+        Control::Continue
+    }
+
+    fn on_train_end(&mut self, _network: &mut NeuralNetwork<T>, _state: &TrainingState<T>) {
+        if let Err(error) = self.flush() {
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn on_epoch_end(&mut self, _network: &mut NeuralNetwork<T>, state: &TrainingState<T>) -> Control {
+        self.log_or_stop(state)
+    }
+
+    fn on_batch_end(&mut self, _network: &mut NeuralNetwork<T>, state: &TrainingState<T>) -> Control {
+        if self.log_batches {
+            self.log_or_stop(state)
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_csv_and_json_lines() {
+        let state = TrainingState {
+            step: 12,
+            epoch: 3,
+            learn_rate: Some(0.5),
+            training_cost: Some(0.25),
+            ..TrainingState::default()
+        };
+
+        let mut csv = MetricsLogger::new(vec![], LogFormat::CSV).unwrap();
+        csv.log(&state).unwrap();
+        let run = csv.run;
+        let csv = String::from_utf8(csv.writer).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert!(lines[1].starts_with(&format!("{},12,3,0.5,,,0.25,,,,", run)));
+
+        let mut jsonl = MetricsLogger::new(vec![], LogFormat::JSONL).unwrap();
+        jsonl.log(&state).unwrap();
+        let run = jsonl.run;
+        let jsonl = String::from_utf8(jsonl.writer).unwrap();
+        assert!(jsonl.starts_with(&format!(
+            "{{\"run\":{},\"step\":12,\"epoch\":3,\"learn_rate\":0.5,\"batch_cost\":null,\"gradient_norm\":null,\"training_cost\":0.25,",
+            run,
+        )));
+        assert!(jsonl.ends_with("}\n"));
+    }
+
+    #[test]
+    fn appends_runs_and_writes_values_that_are_not_finite() {
+        let path = std::env::temp_dir().join(format!("nn-rust-metrics-test-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state = TrainingState {
+            step: 1,
+            epoch: 1,
+            batch_cost: Some(f64::NAN),
+            gradient_norm: Some(f64::INFINITY),
+            training_cost: Some(f64::NEG_INFINITY),
+            ..TrainingState::default()
+        };
+
+        let mut first = MetricsLogger::append(&path, LogFormat::CSV).unwrap();
+        first.log(&state).unwrap();
+        first.flush().unwrap();
+        let mut second = MetricsLogger::append(&path, LogFormat::CSV).unwrap();
+        second.log(&state).unwrap();
+        second.flush().unwrap();
+        assert!(second.run > first.run);
+        assert!(MetricsLogger::append(&path, LogFormat::JSONL).is_err());
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert!(lines[1].starts_with(&format!("{},1,1,,NaN,inf,-inf,", first.run)));
+        assert!(lines[2].starts_with(&format!("{},1,1,,NaN,inf,-inf,", second.run)));
+
+        let mut jsonl = MetricsLogger::new(vec![], LogFormat::JSONL).unwrap();
+        jsonl.log(&state).unwrap();
+        let jsonl = String::from_utf8(jsonl.writer).unwrap();
+        assert!(jsonl.contains("\"batch_cost\":\"NaN\",\"gradient_norm\":\"Infinity\",\"training_cost\":\"-Infinity\","));
+
+        let path = path.with_extension("jsonl");
+        let mut first = MetricsLogger::append(&path, LogFormat::JSONL).unwrap();
+        first.log(&state).unwrap();
+        first.flush().unwrap();
+        let second = MetricsLogger::append(&path, LogFormat::JSONL).unwrap();
+        assert!(second.run > first.run);
+        assert!(MetricsLogger::append(&path, LogFormat::CSV).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,112 +1,683 @@
-use crate::activation::{Activation, Activations, ActivationType};
-use crate::layer::Layer;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::activation::ActivationType;
+#[cfg(test)]
+use crate::autodiff::{Tape, Var};
 use crate::data_point::DataPoint;
+use crate::dense::Dense;
+use crate::float::Float;
+use crate::gradient_clipping::{self, GradientClipping};
+use crate::layer::{self, Layer, Parameters};
+use crate::regularization::Regularization;
+use crate::serialization::{self, RecordReader};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    // Dropout is active and batch normalization uses the batch statistics.
+    Training,
+    // The network is deterministic.
+    Inference,
+}
+
+// A learn step that produced values which are not finite. The parameters and the state of the layers are left as they
+// were before the step.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    // Index of the first layer with non-finite values.
+    pub layer: usize,
+    pub layer_name: &'static str,
+    // Which values are not finite: "outputs", "gradients" or "parameters".
+    pub values: &'static str,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "layer {} ({}) diverged: non-finite {}", self.layer, self.layer_name, self.values)
+    }
+}
+
+// Why a learn step was not taken.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LearnError {
+    Diverged(Divergence),
+    // Data points the network can not take, e.g. embedding indices out of range.
+    InvalidInput(String),
+}
+
+impl fmt::Display for LearnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LearnError::Diverged(divergence) => write!(f, "{}", divergence),
+            LearnError::InvalidInput(message) => write!(f, "invalid input: {}", message),
+        }
+    }
+}
+
+impl From<Divergence> for LearnError {
+    fn from(divergence: Divergence) -> Self {
+        LearnError::Diverged(divergence)
+    }
+}
 
-pub struct NeuralNetwork {
-    pub layers: Vec<Layer>,
-    pub activation: Box<dyn Activation>,
+// Sequential container of layers.
+pub struct NeuralNetwork<T: Float = f32> {
+    // Shape of the inputs of a single data point, e.g. [channels, height, width] for images.
+    pub input_shape: Vec<usize>,
+    pub layers: Vec<Box<dyn Layer<T>>>,
+    pub mode: Mode,
+    pub gradient_clipping: GradientClipping<T>,
 }
 
-impl NeuralNetwork {
-    // Create new neural network.
+impl<T: Float> NeuralNetwork<T> {
+    // Create new neural network of dense layers.
     pub fn new(layer_sizes: Vec<usize>, activation_type: &ActivationType) -> Self {
-        // Do not include the input layer in the layers: -1
-        let layer_count = layer_sizes.len() - 1;
-        let mut layers: Vec<Layer> = Vec::with_capacity(layer_count);
+        let mut network = Self::sequential(vec![layer_sizes[0]]);
 
-        for i in 0..layer_count {
-            layers.push(Layer::new(layer_sizes[i], layer_sizes[i + 1]));
+        // Do not include the input layer in the layers: -1
+        for i in 0..layer_sizes.len() - 1 {
+            network.add(Box::new(Dense::new(layer_sizes[i], layer_sizes[i + 1], activation_type)));
         }
 
-        let activation = Activations::get_activation(activation_type);
+        network
+    }
 
+    // Create a network without layers, taking inputs of the given shape.
+    pub fn sequential(input_shape: Vec<usize>) -> Self {
         NeuralNetwork {
-            layers,
-            activation,
+            input_shape,
+            layers: vec![],
+            mode: Mode::Inference,
+            gradient_clipping: GradientClipping::none(),
         }
     }
 
-    pub fn learn(&mut self, training_data: &Vec<DataPoint>, learn_rate: f32, h: f32) {
-        let original_cost = self.cost(training_data);
+    // Shape of the outputs of the last layer, which is the input shape of the next layer to add.
+    pub fn output_shape(&self) -> Vec<usize> {
+        self.layers.iter()
+            .fold(self.input_shape.clone(), |shape, layer| layer.output_shape(&shape))
+    }
 
-        // Clone layers to use for iterating with a immutable reference.
-        let layers = self.layers.clone();
+    // Append a layer to the end of the network.
+    pub fn add(&mut self, layer: Box<dyn Layer<T>>) {
+        self.layers.push(layer);
+    }
 
-        for layer_index in 0..layers.len() {
-            let layer = &layers[layer_index];
+    // Insert a layer before the layer at the given index.
+    pub fn insert(&mut self, index: usize, layer: Box<dyn Layer<T>>) {
+        self.layers.insert(index, layer);
+    }
 
-            // Calculate the cost gradient for the current weights.
-            for node_in in 0..layer.num_nodes_in {
-                for node_out in 0..layer.num_nodes_out {
-                    self.layers[layer_index].weights[node_in][node_out] += h;
-                    let delta_cost = self.cost(training_data) - original_cost;
-                    self.layers[layer_index].weights[node_in][node_out] -= h;
-                    self.layers[layer_index].cost_gradient_weights[node_in][node_out] = delta_cost / h;
-                }
+    // Apply the same regularization to all layers.
+    pub fn set_regularization(&mut self, regularization: Regularization<T>) {
+        for layer in &mut self.layers {
+            layer.set_regularization(&regularization);
+        }
+    }
+
+    // Run one iteration of gradient descent on the training data, using backpropagation for the gradients.
+    // Returns the cost of the batch before the update, like `cost`: the average data cost plus the regularization penalty.
+    pub fn learn(&mut self, training_data: &Vec<DataPoint<T>>, learn_rate: T) -> Result<T, LearnError> {
+        self.learn_with_output_gradients(training_data, learn_rate, |outputs, data_point| {
+            let pairs = outputs.iter().zip(&data_point.expected_outputs);
+            let cost = pairs.clone().map(|(&output, &expected_output)| Self::node_cost(output, expected_output)).sum();
+
+            (cost, pairs.map(|(&output, &expected_output)| Self::node_cost_derivative(output, expected_output)).collect())
+        })
+    }
+
+    // Like `learn`, but minimizing a custom cost of a single data point, given its outputs and expected outputs.
+    // The gradients of the cost come from automatic differentiation.
+    #[cfg(test)]
+    pub fn learn_with_cost<C>(&mut self, training_data: &Vec<DataPoint<T>>, learn_rate: T, cost: C) -> Result<T, LearnError>
+    where
+        C: for<'t> Fn(&[Var<'t, T>], &[T]) -> Var<'t, T>,
+    {
+        self.learn_with_output_gradients(training_data, learn_rate, |outputs, data_point| {
+            let tape = Tape::new();
+            let outputs = tape.vars(outputs);
+            let cost = cost(&outputs, &data_point.expected_outputs);
+            let gradients = cost.gradients();
+
+            (cost.value, outputs.iter().map(|output| gradients.wrt(output)).collect())
+        })
+    }
+
+    // The output gradients give the cost of a single data point and its gradients with respect to the outputs.
+    fn learn_with_output_gradients<G>(&mut self, training_data: &Vec<DataPoint<T>>, learn_rate: T, output_gradients: G) -> Result<T, LearnError>
+    where
+        G: Fn(&[T], &DataPoint<T>) -> (T, Vec<T>),
+    {
+        self.check_inputs(training_data).map_err(LearnError::InvalidInput)?;
+
+        // The forward pass already changes the state of layers, so it is restored as well if the step diverges.
+        let last_good = self.snapshot();
+        let mode = self.mode;
+        self.mode = Mode::Training;
+        let result = self.backpropagate(training_data, output_gradients);
+        self.mode = mode;
+
+        let result = result.and_then(|cost| {
+            let cost = cost + self.regularization_cost();
+            self.add_regularization_gradients();
+            self.clip_gradients();
+            self.apply_all_gradients(learn_rate).map(|()| cost)
+        });
+        if result.is_err() {
+            self.restore(&last_good);
+        }
+
+        Ok(result?)
+    }
+
+    // Why data points can not be taken by the network, if they can not: values for a network starting with a layer
+    // that takes indices or the other way around, or indices the first layer has no values for.
+    pub fn check_inputs(&self, data: &[DataPoint<T>]) -> Result<(), String> {
+        let takes_indices = self.layers.first().is_some_and(|layer| layer.takes_indices());
+
+        for (index, data_point) in data.iter().enumerate() {
+            if data_point.indices.is_empty() == takes_indices {
+                let (found, expected) = if takes_indices { ("values", "indices") } else { ("indices", "values") };
+                return Err(format!("data point {} has {}, the network takes {}", index, found, expected));
+            }
+            if takes_indices {
+                self.layers[0].check_indices(&data_point.indices).map_err(|message| format!("data point {}: {}", index, message))?;
             }
+        }
+
+        Ok(())
+    }
 
-            // Calculate the cost gradient for the current biases.
-            for node_out in 0..layer.num_nodes_out {
-                self.layers[layer_index].biases[node_out] += h;
-                let delta_cost = self.cost(training_data) - original_cost;
-                self.layers[layer_index].biases[node_out] -= h;
-                self.layers[layer_index].cost_gradient_biases[node_out] = delta_cost / h;
+    // Calculate the cost gradients of all layers, stopping at the first layer with non-finite values.
+    // Returns the average cost.
+    fn backpropagate<G>(&mut self, training_data: &Vec<DataPoint<T>>, output_gradients: G) -> Result<T, Divergence>
+    where
+        G: Fn(&[T], &DataPoint<T>) -> (T, Vec<T>),
+    {
+        let mut outputs: Vec<Vec<T>> = training_data.iter().map(|data_point| data_point.inputs.to_vec()).collect();
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            outputs = Self::forward_layer(layer.as_mut(), outputs, training_data, true);
+            if !outputs.iter().flatten().all(|output| output.is_finite()) {
+                return Err(Self::divergence(index, layer.as_ref(), "outputs"));
             }
         }
 
-        self.apply_all_gradients(learn_rate);
+        // Gradients of the average cost with respect to the outputs.
+        let data_len = T::of(training_data.len() as f64);
+        let mut cost = T::zero();
+        let mut gradients: Vec<Vec<T>> = outputs.iter()
+            .zip(training_data)
+            .map(|(outputs, data_point)| {
+                let (data_point_cost, gradients) = output_gradients(outputs, data_point);
+                cost += data_point_cost / data_len;
+
+                gradients.into_iter().map(|gradient| gradient / data_len).collect()
+            })
+            .collect();
+
+        for (index, layer) in self.layers.iter_mut().enumerate().rev() {
+            gradients = layer.backward(gradients);
+
+            let finite = gradients.iter().flatten().all(|gradient| gradient.is_finite())
+                && layer.parameters().iter().all(|parameters| parameters.gradients.iter().all(|gradient| gradient.is_finite()));
+            if !finite {
+                return Err(Self::divergence(index, layer.as_ref(), "gradients"));
+            }
+        }
+
+        Ok(cost)
+    }
+
+    fn divergence(index: usize, layer: &dyn Layer<T>, values: &'static str) -> Divergence {
+        Divergence { layer: index, layer_name: layer.name(), values }
     }
 
-    fn apply_all_gradients(&mut self, learn_rate: f32) {
+    // Add the derivatives of the regularization penalties to the cost gradients.
+    fn add_regularization_gradients(&mut self) {
         for layer in &mut self.layers {
-            layer.apply_gradients(learn_rate);
+            for parameters in layer.parameters() {
+                if let Some(regularization) = parameters.regularization {
+                    for (gradient, &value) in parameters.gradients.iter_mut().zip(parameters.values.iter()) {
+                        *gradient += regularization.penalty_derivative(value);
+                    }
+                }
+            }
         }
     }
 
-    // Run the input values through the network to calculate the output values.
-    pub fn calculate_outputs(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+    // Scale the cost gradients of all layers down, as configured in `gradient_clipping`.
+    fn clip_gradients(&mut self) {
+        let mut parameters: Vec<Parameters<'_, T>> = self.layers.iter_mut().flat_map(|layer| layer.parameters()).collect();
+
+        self.gradient_clipping.clip(&mut parameters);
+    }
+
+    // Global norm of the cost gradients of the last learn step, after clipping.
+    pub fn gradient_norm(&mut self) -> T {
+        let parameters: Vec<Parameters<'_, T>> = self.layers.iter_mut().flat_map(|layer| layer.parameters()).collect();
+
+        gradient_clipping::global_norm_of(&parameters)
+    }
+
+    // Update all parameters based on their cost gradients (gradient descent).
+    // Fails if any parameter becomes non-finite.
+    fn apply_all_gradients(&mut self, learn_rate: T) -> Result<(), Divergence> {
+        for layer in &mut self.layers {
+            for parameters in layer.parameters() {
+                for (value, &gradient) in parameters.values.iter_mut().zip(parameters.gradients.iter()) {
+                    if let Some(regularization) = parameters.regularization {
+                        *value = regularization.decay(*value, learn_rate);
+                    }
+                    *value -= gradient * learn_rate;
+                }
+            }
+        }
+
+        let diverged = self.layers.iter_mut()
+            .position(|layer| !layer.values().iter().all(|values| values.iter().all(|value| value.is_finite())));
+
+        match diverged {
+            Some(index) => Err(Self::divergence(index, self.layers[index].as_ref(), "parameters")),
+            None => Ok(()),
+        }
+    }
+
+    // Copy of the trainable values of all layers, followed by their state.
+    pub fn snapshot(&mut self) -> Vec<Vec<Vec<T>>> {
+        self.layers.iter_mut()
+            .map(|layer| {
+                let mut values: Vec<Vec<T>> = layer.values().iter().map(|values| values.to_vec()).collect();
+                values.extend(layer.state().iter().map(|state| state.to_vec()));
+                values
+            })
+            .collect()
+    }
+
+    // Set the trainable values and the state of all layers to a snapshot of this network.
+    pub fn restore(&mut self, snapshot: &[Vec<Vec<T>>]) {
+        for (layer, layer_snapshot) in self.layers.iter_mut().zip(snapshot) {
+            let mut snapshot_values = layer_snapshot.iter();
+            for values in layer.values() {
+                values.copy_from_slice(snapshot_values.next().unwrap());
+            }
+            for state in layer.state() {
+                state.copy_from_slice(snapshot_values.next().unwrap());
+            }
+        }
+    }
+
+    // Run a batch of inputs through the network to calculate the output values.
+    pub fn forward(&mut self, inputs: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let training = self.mode == Mode::Training;
         let mut outputs = inputs;
 
-        for layer_index in 0..self.layers.len() {
-            self.layers[layer_index].calculate_outputs(outputs, &self.activation);
+        for layer in &mut self.layers {
+            outputs = layer.forward(outputs, training);
+        }
 
-            outputs = self.layers[layer_index].activations.clone();
+        outputs
+    }
+
+    // Run a batch of data points through the network, see `check_inputs` for the data points it can take.
+    pub fn forward_data(&mut self, data: &[DataPoint<T>]) -> Vec<Vec<T>> {
+        let training = self.mode == Mode::Training;
+        let mut outputs = data.iter().map(|data_point| data_point.inputs.to_vec()).collect();
+
+        for layer in &mut self.layers {
+            outputs = Self::forward_layer(layer.as_mut(), outputs, data, training);
         }
 
         outputs
     }
 
+    // A layer taking indices takes those of the data points, other layers take the outputs of the layer before.
+    fn forward_layer(layer: &mut dyn Layer<T>, inputs: Vec<Vec<T>>, data: &[DataPoint<T>], training: bool) -> Vec<Vec<T>> {
+        if layer.takes_indices() {
+            let indices: Vec<Vec<usize>> = data.iter().map(|data_point| data_point.indices.clone()).collect();
+            layer.forward_indices(&indices, training)
+        } else {
+            layer.forward(inputs, training)
+        }
+    }
+
+    // Outputs of every layer for a single data point, preceded by the inputs.
+    pub fn layer_outputs(&mut self, inputs: Vec<T>) -> Vec<Vec<T>> {
+        let training = self.mode == Mode::Training;
+        let mut outputs = vec![inputs];
+
+        for layer in &mut self.layers {
+            let layer_outputs = layer.forward(vec![outputs.last().unwrap().clone()], training).pop().unwrap();
+            outputs.push(layer_outputs);
+        }
+
+        outputs
+    }
+
+    // Run the input values through the network to calculate the output values.
+    pub fn calculate_outputs(&mut self, inputs: Vec<T>) -> Vec<T> {
+        self.forward(vec![inputs]).pop().unwrap()
+    }
+
     // Run the inputs through the network and calculate which output node has the highest value.
-    pub fn classify(&mut self, inputs: Vec<f32>) -> Option<usize> {
-        let outputs = self.calculate_outputs(inputs);
+    pub fn classify(&mut self, inputs: Vec<T>) -> Option<usize> {
+        Self::max_index(&self.calculate_outputs(inputs))
+    }
 
+    fn max_index(outputs: &[T]) -> Option<usize> {
         outputs.iter()
             .enumerate()
-            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
             .map(|(index, _)| index)
     }
 
-    pub fn cost(&mut self, data: &Vec<DataPoint>) -> f32 {
-        let mut total_cost: f32 = 0.0;
-        let data_len = data.len() as f32;
+    // Fraction of the data points classified as their label.
+    pub fn accuracy(&mut self, data: &Vec<DataPoint<T>>) -> T {
+        let outputs = self.forward_data(data);
+
+        let correct = outputs.iter()
+            .zip(data)
+            .filter(|(outputs, data_point)| Self::max_index(outputs) == Some(data_point.label))
+            .count();
+
+        T::of(correct as f64) / T::of(data.len() as f64)
+    }
+
+    // Data cost plus the regularization penalty of all layers.
+    pub fn cost(&mut self, data: &Vec<DataPoint<T>>) -> T {
+        self.data_cost(data) + self.regularization_cost()
+    }
+
+    pub fn regularization_cost(&mut self) -> T {
+        let mut cost = T::zero();
 
-        for data_point in data {
-            total_cost += self.cost_single(data_point);
+        for layer in &mut self.layers {
+            for parameters in layer.parameters() {
+                if let Some(regularization) = parameters.regularization {
+                    cost += parameters.values.iter().map(|&value| regularization.penalty(value)).sum();
+                }
+            }
+        }
+
+        cost
+    }
+
+    fn data_cost(&mut self, data: &Vec<DataPoint<T>>) -> T {
+        let outputs = self.forward_data(data);
+
+        let mut total_cost = T::zero();
+        let data_len = T::of(data.len() as f64);
+
+        for (outputs, data_point) in outputs.iter().zip(data) {
+            for node_out in 0..outputs.len() {
+                total_cost += Self::node_cost(outputs[node_out], data_point.expected_outputs[node_out]);
+            }
         }
 
         total_cost / data_len
     }
 
-    fn cost_single(&mut self, data_point: &DataPoint) -> f32 {
-        let outputs = self.calculate_outputs(data_point.inputs.to_vec());
-        let output_layer = &self.layers.last().unwrap();
-        let mut cost: f32 = 0.0;
+    fn node_cost(output_activation: T, expected_output: T) -> T {
+        let error = output_activation - expected_output;
 
-        for node_out in 0..outputs.len() {
-            let current_node_cost = output_layer.node_cost(outputs[node_out], data_point.expected_outputs[node_out]);
-            cost += current_node_cost;
+        error * error
+    }
+
+    fn node_cost_derivative(output_activation: T, expected_output: T) -> T {
+        T::of(2.0) * (output_activation - expected_output)
+    }
+
+    // Save the layers and their parameters to a text file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        serialization::write_record(&mut writer, "network", &[self.layers.len().to_string()])?;
+        serialization::write_record(&mut writer, "input_shape", &self.input_shape.iter().map(|size| size.to_string()).collect::<Vec<String>>())?;
+        for layer in &self.layers {
+            layer::save(layer.as_ref(), &mut writer)?;
         }
 
-        cost
+        Ok(())
+    }
+
+    // Load a network saved with `save`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = RecordReader::new(BufReader::new(File::open(path)?));
+
+        let network = reader.record("network")?;
+        let layer_count: usize = serialization::parse(network.first(), "network")?;
+        let input_shape = reader.record("input_shape")?
+            .iter()
+            .map(|size| serialization::parse(Some(size), "input_shape"))
+            .collect::<io::Result<Vec<usize>>>()?;
+
+        let mut neural_network = Self::sequential(input_shape);
+        for _ in 0..layer_count {
+            neural_network.add(layer::load(&mut reader)?);
+        }
+
+        Ok(neural_network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autodiff::{Expression, ExpressionLayer};
+    use crate::convolution::{Conv2D, Flatten, PoolType, Pool2D};
+    use crate::dropout::Dropout;
+    use crate::normalization::{BatchNorm, LayerNorm};
+    use crate::recurrent::{CellType, Recurrent};
+
+    #[test]
+    fn inference_is_deterministic() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 8, 2], &ActivationType::SIGMOID);
+        network.insert(1, Box::new(Dropout::new(0.5, 42)));
+
+        let first = network.calculate_outputs(vec![0.3, 0.7]);
+        let second = network.calculate_outputs(vec![0.3, 0.7]);
+        assert_eq!(first, second);
+
+        network.mode = Mode::Training;
+        let dropped = (0..10).any(|_| network.calculate_outputs(vec![0.3, 0.7]) != first);
+        assert!(dropped);
+    }
+
+    #[test]
+    fn backpropagation_matches_finite_differences() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 4, 3, 2], &ActivationType::TANH);
+        network.insert(1, Box::new(BatchNorm::new(4)));
+        network.insert(3, Box::new(LayerNorm::new(3)));
+
+        let data = vec![
+            DataPoint::new(vec![0.1, 0.9], 0, 2),
+            DataPoint::new(vec![0.7, 0.2], 1, 2),
+            DataPoint::new(vec![0.4, 0.5], 1, 2),
+        ];
+
+        assert_gradients_match(&mut network, &data);
+        // Batch normalization of a single data point.
+        assert_gradients_match(&mut network, &data[..1].to_vec());
+    }
+
+    #[test]
+    fn regularization_gradients_match_finite_differences() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 4, 2], &ActivationType::TANH);
+        network.set_regularization(Regularization::new(0.01, 0.05, 0.0, true));
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+
+        assert!(network.regularization_cost() > 0.0);
+        assert_gradients_match(&mut network, &data);
+    }
+
+    #[test]
+    fn learn_reports_the_cost_with_the_penalty() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 3, 2], &ActivationType::TANH);
+        network.set_regularization(Regularization::new(0.01, 0.1, 0.0, true));
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+        let cost = network.cost(&data);
+
+        assert!(network.regularization_cost() > 0.0);
+        assert!((network.learn(&data, 0.5).unwrap() - cost).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weight_decay_shrinks_parameters_before_the_gradient_step() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 3, 2], &ActivationType::TANH);
+        network.set_regularization(Regularization::new(0.0, 0.0, 0.1, false));
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+        let before: Vec<Vec<f64>> = network.layers[0].parameters().iter().map(|parameters| parameters.values.to_vec()).collect();
+
+        // Decay does not change the cost or the gradients.
+        assert_eq!(network.regularization_cost(), 0.0);
+        network.learn(&data, 0.5).unwrap();
+
+        let parameters = network.layers[0].parameters();
+        let (weights, biases) = (&parameters[0], parameters.last().unwrap());
+        for (index, &value) in weights.values.iter().enumerate() {
+            let old = before[0][index];
+            assert!((value - (old - old * 0.1 * 0.5 - weights.gradients[index] * 0.5)).abs() < 1e-12);
+        }
+        // Biases are only decayed when they are included.
+        for (index, &value) in biases.values.iter().enumerate() {
+            let old = before.last().unwrap()[index];
+            assert!((value - (old - biases.gradients[index] * 0.5)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn convolution_gradients_match_finite_differences() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2, 8, 6]);
+        network.add(Box::new(Conv2D::new(&network.output_shape(), 3, 3, 1, 1, &ActivationType::TANH)));
+        network.add(Box::new(Pool2D::new(&network.output_shape(), &PoolType::MAX, 2, 2)));
+        network.add(Box::new(Conv2D::new(&network.output_shape(), 2, 2, 1, 0, &ActivationType::SILU)));
+        network.add(Box::new(Pool2D::new(&network.output_shape(), &PoolType::AVERAGE, 2, 1)));
+        network.add(Box::new(Flatten));
+        network.add(Box::new(Dense::new(network.output_shape()[0], 2, &ActivationType::SIGMOID)));
+
+        let data: Vec<DataPoint<f64>> = (0..2)
+            .map(|label| DataPoint::new((0..96).map(|_| f64::random()).collect(), label, 2))
+            .collect();
+
+        assert_gradients_match(&mut network, &data);
+    }
+
+    #[test]
+    fn recurrent_gradients_match_finite_differences() {
+        for cell_type in [CellType::RNN, CellType::GRU, CellType::LSTM] {
+            let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![4, 3]);
+            network.add(Box::new(Recurrent::new(&network.output_shape(), &cell_type, 5, true)));
+            network.add(Box::new(Recurrent::new(&network.output_shape(), &cell_type, 4, false)));
+            network.add(Box::new(Dense::new(4, 2, &ActivationType::SIGMOID)));
+
+            let data: Vec<DataPoint<f64>> = (0..2)
+                .map(|label| DataPoint::new((0..12).map(|_| f64::random()).collect(), label, 2))
+                .collect();
+
+            assert_gradients_match(&mut network, &data);
+        }
+    }
+
+    #[test]
+    fn expression_gradients_match_finite_differences() {
+        // Dense layer, with the backward pass coming from the tape.
+        let dense: Dense<f64> = Dense::new(3, 2, &ActivationType::SILU);
+        let mut parameters = dense.weights.clone();
+        parameters.push(dense.biases.clone());
+        let expression: Expression<f64> = Box::new(move |parameters, inputs| dense.expression(&parameters[..3], &parameters[3], inputs));
+
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 3], &ActivationType::TANH);
+        network.add(Box::new(ExpressionLayer::new(vec![2], parameters, expression)));
+
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+
+        assert_gradients_match(&mut network, &data);
+    }
+
+    #[test]
+    fn custom_cost_learns_like_the_built_in_cost() {
+        let create = || {
+            let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2]);
+            network.add(Box::new(Dense::seeded(2, 3, &ActivationType::TANH, 1)));
+            network.add(Box::new(Dense::seeded(3, 2, &ActivationType::SIGMOID, 2)));
+            network
+        };
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+
+        let mut network = create();
+        network.learn(&data, 0.5).unwrap();
+        // The same squared error, on the tape.
+        let mut custom = create();
+        custom.learn_with_cost(&data, 0.5, |outputs, expected_outputs| outputs.iter()
+            .zip(expected_outputs)
+            .map(|(&output, &expected_output)| (output - expected_output).powi(2))
+            .reduce(|sum, cost| sum + cost)
+            .unwrap()).unwrap();
+
+        let (outputs, custom_outputs) = (network.calculate_outputs(vec![0.4, 0.5]), custom.calculate_outputs(vec![0.4, 0.5]));
+        for (output, custom_output) in outputs.iter().zip(&custom_outputs) {
+            assert!((output - custom_output).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn divergence_restores_last_good_parameters() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 3, 2], &ActivationType::TANH);
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+        let outputs = network.calculate_outputs(vec![0.1, 0.9]);
+
+        let divergence = match network.learn(&data, f64::INFINITY) {
+            Err(LearnError::Diverged(divergence)) => divergence,
+            result => panic!("expected a divergence, got {:?}", result),
+        };
+
+        assert_eq!(divergence.values, "parameters");
+        assert_eq!(divergence.layer_name, "dense");
+        assert_eq!(network.calculate_outputs(vec![0.1, 0.9]), outputs);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn divergence_restores_batch_norm_statistics() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2]);
+        network.add(Box::new(BatchNorm::new(2)));
+        network.add(Box::new(Dense::new(2, 2, &ActivationType::TANH)));
+        let data = vec![DataPoint::new(vec![0.1, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+        network.learn(&data, 0.5).unwrap();
+        let outputs = network.calculate_outputs(vec![0.1, 0.9]);
+
+        // The batch statistics of a non-finite input get into the running statistics during the forward pass.
+        let poisoned = vec![DataPoint::new(vec![f64::NAN, 0.9], 0, 2), DataPoint::new(vec![0.7, 0.2], 1, 2)];
+        let divergence = match network.learn(&poisoned, 0.5) {
+            Err(LearnError::Diverged(divergence)) => divergence,
+            result => panic!("expected a divergence, got {:?}", result),
+        };
+
+        assert_eq!((divergence.layer_name, divergence.values), ("batch_norm", "outputs"));
+        assert_eq!(network.calculate_outputs(vec![0.1, 0.9]), outputs);
+    }
+
+    fn assert_gradients_match(network: &mut NeuralNetwork<f64>, data: &Vec<DataPoint<f64>>) {
+        // Learning with a learn rate of zero only calculates the gradients.
+        network.learn(data, 0.0).unwrap();
+        network.mode = Mode::Training;
+
+        let h = 1e-6;
+        for layer_index in 0..network.layers.len() {
+            for group in 0..network.layers[layer_index].parameters().len() {
+                for index in 0..network.layers[layer_index].parameters()[group].values.len() {
+                    network.layers[layer_index].parameters()[group].values[index] += h;
+                    let cost_above = network.cost(data);
+                    network.layers[layer_index].parameters()[group].values[index] -= 2.0 * h;
+                    let cost_below = network.cost(data);
+                    network.layers[layer_index].parameters()[group].values[index] += h;
+                    let slope = (cost_above - cost_below) / (2.0 * h);
+
+                    let gradient = network.layers[layer_index].parameters()[group].gradients[index];
+                    assert!((slope - gradient).abs() < 1e-5 * (1.0 + gradient.abs()), "{} != {}", slope, gradient);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::io::{self, BufRead, Write};
+
+use crate::float::Float;
+use crate::layer::{self, Layer, Parameters};
+use crate::serialization::{self, RecordReader};
+
+const EPSILON: f64 = 1e-5;
+// How much of the running statistics is kept on every training batch.
+const MOMENTUM: f64 = 0.9;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NormalizationType {
+    NONE,
+    BATCH,
+    LAYER,
+}
+
+// Normalizes every node over the batch, using running statistics at inference time.
+// A training batch of a single data point has no variance to normalize with, so it is normalized with the running
+// statistics like at inference time, and leaves them unchanged.
+pub struct BatchNorm<T: Float = f32> {
+    pub gamma: Vec<T>,
+    pub beta: Vec<T>,
+    pub cost_gradient_gamma: Vec<T>,
+    pub cost_gradient_beta: Vec<T>,
+    pub running_mean: Vec<T>,
+    pub running_variance: Vec<T>,
+    pub momentum: T,
+    pub epsilon: T,
+    // Learn data of the last training batch.
+    normalized: Vec<Vec<T>>,
+    inverse_deviations: Vec<T>,
+    // Whether the last batch was normalized with its own statistics, which the backward pass depends on.
+    batch_statistics: bool,
+}
+
+// Normalizes every data point over its nodes, the same way in training and inference.
+pub struct LayerNorm<T: Float = f32> {
+    pub gamma: Vec<T>,
+    pub beta: Vec<T>,
+    pub cost_gradient_gamma: Vec<T>,
+    pub cost_gradient_beta: Vec<T>,
+    pub epsilon: T,
+    // Learn data of the last batch.
+    normalized: Vec<Vec<T>>,
+    inverse_deviations: Vec<T>,
+}
+
+impl<T: Float> BatchNorm<T> {
+    pub fn new(num_nodes: usize) -> Self {
+        BatchNorm {
+            gamma: vec![T::one(); num_nodes],
+            beta: vec![T::zero(); num_nodes],
+            cost_gradient_gamma: vec![T::zero(); num_nodes],
+            cost_gradient_beta: vec![T::zero(); num_nodes],
+            running_mean: vec![T::zero(); num_nodes],
+            running_variance: vec![T::one(); num_nodes],
+            momentum: T::of(MOMENTUM),
+            epsilon: T::of(EPSILON),
+            normalized: vec![],
+            inverse_deviations: vec![],
+            batch_statistics: false,
+        }
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let gamma: Vec<T> = reader.values("gamma")?;
+        let num_nodes = gamma.len();
+
+        let mut batch_norm = BatchNorm::new(num_nodes);
+        batch_norm.gamma = gamma;
+        batch_norm.beta = layer::read_exact(reader, "beta", num_nodes)?;
+        batch_norm.running_mean = layer::read_exact(reader, "running_mean", num_nodes)?;
+        batch_norm.running_variance = layer::read_exact(reader, "running_variance", num_nodes)?;
+
+        Ok(batch_norm)
+    }
+}
+
+impl<T: Float> Layer<T> for BatchNorm<T> {
+    fn name(&self) -> &'static str {
+        "batch_norm"
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        input_shape.to_vec()
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, training: bool) -> Vec<Vec<T>> {
+        let num_nodes = self.gamma.len();
+
+        self.batch_statistics = training && inputs.len() > 1;
+
+        let (mean, variance) = if self.batch_statistics {
+            let batch_size = T::of(inputs.len() as f64);
+            let mut mean = vec![T::zero(); num_nodes];
+            let mut variance = vec![T::zero(); num_nodes];
+
+            for node in 0..num_nodes {
+                mean[node] = inputs.iter().map(|input| input[node]).sum::<T>() / batch_size;
+                variance[node] = inputs.iter().map(|input| (input[node] - mean[node]).powi(2)).sum::<T>() / batch_size;
+
+                self.running_mean[node] = self.momentum * self.running_mean[node] + (T::one() - self.momentum) * mean[node];
+                self.running_variance[node] = self.momentum * self.running_variance[node] + (T::one() - self.momentum) * variance[node];
+            }
+
+            (mean, variance)
+        } else {
+            (self.running_mean.clone(), self.running_variance.clone())
+        };
+
+        self.inverse_deviations = variance.iter()
+            .map(|&variance| T::one() / (variance + self.epsilon).sqrt())
+            .collect();
+
+        self.normalized = inputs.iter()
+            .map(|input| (0..num_nodes).map(|node| (input[node] - mean[node]) * self.inverse_deviations[node]).collect())
+            .collect();
+
+        scale_and_shift(&self.normalized, &self.gamma, &self.beta)
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let num_nodes = self.gamma.len();
+        let batch_size = T::of(output_gradients.len() as f64);
+
+        accumulate_gradients(&self.normalized, &output_gradients, &mut self.cost_gradient_gamma, &mut self.cost_gradient_beta);
+
+        // Fixed statistics make the layer a per node scale.
+        if !self.batch_statistics {
+            return output_gradients.iter()
+                .map(|gradients| (0..num_nodes).map(|node| gradients[node] * self.gamma[node] * self.inverse_deviations[node]).collect())
+                .collect();
+        }
+
+        let mut input_gradients = vec![vec![T::zero(); num_nodes]; output_gradients.len()];
+
+        for node in 0..num_nodes {
+            // Gradients with respect to the normalized values.
+            let normalized_gradients: Vec<T> = output_gradients.iter()
+                .map(|gradients| gradients[node] * self.gamma[node])
+                .collect();
+            let sum: T = normalized_gradients.iter().copied().sum();
+            let sum_scaled: T = normalized_gradients.iter()
+                .zip(&self.normalized)
+                .map(|(&gradient, normalized)| gradient * normalized[node])
+                .sum();
+
+            for (index, gradients) in input_gradients.iter_mut().enumerate() {
+                gradients[node] = self.inverse_deviations[node] / batch_size
+                    * (batch_size * normalized_gradients[index] - sum - self.normalized[index][node] * sum_scaled);
+            }
+        }
+
+        input_gradients
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        scale_and_shift_parameters(&mut self.gamma, &mut self.beta, &mut self.cost_gradient_gamma, &mut self.cost_gradient_beta)
+    }
+
+    fn state(&mut self) -> Vec<&mut [T]> {
+        vec![&mut self.running_mean, &mut self.running_variance]
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_values(writer, "gamma", &self.gamma)?;
+        serialization::write_values(writer, "beta", &self.beta)?;
+        serialization::write_values(writer, "running_mean", &self.running_mean)?;
+        serialization::write_values(writer, "running_variance", &self.running_variance)
+    }
+}
+
+impl<T: Float> LayerNorm<T> {
+    pub fn new(num_nodes: usize) -> Self {
+        LayerNorm {
+            gamma: vec![T::one(); num_nodes],
+            beta: vec![T::zero(); num_nodes],
+            cost_gradient_gamma: vec![T::zero(); num_nodes],
+            cost_gradient_beta: vec![T::zero(); num_nodes],
+            epsilon: T::of(EPSILON),
+            normalized: vec![],
+            inverse_deviations: vec![],
+        }
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let gamma: Vec<T> = reader.values("gamma")?;
+
+        let mut layer_norm = LayerNorm::new(gamma.len());
+        layer_norm.beta = layer::read_exact(reader, "beta", gamma.len())?;
+        layer_norm.gamma = gamma;
+
+        Ok(layer_norm)
+    }
+}
+
+impl<T: Float> Layer<T> for LayerNorm<T> {
+    fn name(&self) -> &'static str {
+        "layer_norm"
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        input_shape.to_vec()
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        let num_nodes = T::of(self.gamma.len() as f64);
+
+        self.normalized = Vec::with_capacity(inputs.len());
+        self.inverse_deviations = Vec::with_capacity(inputs.len());
+
+        for input in &inputs {
+            let mean = input.iter().copied().sum::<T>() / num_nodes;
+            let variance = input.iter().map(|&value| (value - mean).powi(2)).sum::<T>() / num_nodes;
+            let inverse_deviation = T::one() / (variance + self.epsilon).sqrt();
+
+            self.normalized.push(input.iter().map(|&value| (value - mean) * inverse_deviation).collect());
+            self.inverse_deviations.push(inverse_deviation);
+        }
+
+        scale_and_shift(&self.normalized, &self.gamma, &self.beta)
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let num_nodes = T::of(self.gamma.len() as f64);
+
+        accumulate_gradients(&self.normalized, &output_gradients, &mut self.cost_gradient_gamma, &mut self.cost_gradient_beta);
+
+        output_gradients.iter()
+            .enumerate()
+            .map(|(index, gradients)| {
+                let normalized = &self.normalized[index];
+                // Gradients with respect to the normalized values.
+                let normalized_gradients: Vec<T> = gradients.iter()
+                    .zip(&self.gamma)
+                    .map(|(&gradient, &gamma)| gradient * gamma)
+                    .collect();
+                let sum: T = normalized_gradients.iter().copied().sum();
+                let sum_scaled: T = normalized_gradients.iter()
+                    .zip(normalized)
+                    .map(|(&gradient, &normalized)| gradient * normalized)
+                    .sum();
+
+                normalized_gradients.iter()
+                    .zip(normalized)
+                    .map(|(&gradient, &normalized)| self.inverse_deviations[index] / num_nodes
+                        * (num_nodes * gradient - sum - normalized * sum_scaled))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        scale_and_shift_parameters(&mut self.gamma, &mut self.beta, &mut self.cost_gradient_gamma, &mut self.cost_gradient_beta)
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_values(writer, "gamma", &self.gamma)?;
+        serialization::write_values(writer, "beta", &self.beta)
+    }
+}
+
+// Create the normalization layer of the given type, if any.
+pub fn create<T: Float>(normalization_type: &NormalizationType, num_nodes: usize) -> Option<Box<dyn Layer<T>>> {
+    match normalization_type {
+        NormalizationType::NONE => None,
+        NormalizationType::BATCH => Some(Box::new(BatchNorm::new(num_nodes))),
+        NormalizationType::LAYER => Some(Box::new(LayerNorm::new(num_nodes))),
+    }
+}
+
+fn scale_and_shift<T: Float>(normalized: &[Vec<T>], gamma: &[T], beta: &[T]) -> Vec<Vec<T>> {
+    normalized.iter()
+        .map(|values| values.iter()
+            .enumerate()
+            .map(|(node, &value)| gamma[node] * value + beta[node])
+            .collect())
+        .collect()
+}
+
+fn accumulate_gradients<T: Float>(normalized: &[Vec<T>], output_gradients: &[Vec<T>], cost_gradient_gamma: &mut [T], cost_gradient_beta: &mut [T]) {
+    cost_gradient_gamma.fill(T::zero());
+    cost_gradient_beta.fill(T::zero());
+
+    for (values, gradients) in normalized.iter().zip(output_gradients) {
+        for node in 0..gradients.len() {
+            cost_gradient_gamma[node] += gradients[node] * values[node];
+            cost_gradient_beta[node] += gradients[node];
+        }
+    }
+}
+
+fn scale_and_shift_parameters<'a, T: Float>(gamma: &'a mut [T], beta: &'a mut [T], cost_gradient_gamma: &'a mut [T], cost_gradient_beta: &'a mut [T]) -> Vec<Parameters<'a, T>> {
+    vec![
+        Parameters { values: gamma, gradients: cost_gradient_gamma, regularization: None },
+        Parameters { values: beta, gradients: cost_gradient_beta, regularization: None },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_in_training_and_inference_and_saves() {
+        let inputs = vec![vec![1.0, 10.0], vec![3.0, 10.0]];
+
+        let mut batch_norm: BatchNorm<f64> = BatchNorm::new(2);
+        let outputs = batch_norm.forward(inputs.clone(), true);
+        assert!((outputs[0][0] + 1.0).abs() < 1e-4 && (outputs[1][0] - 1.0).abs() < 1e-4);
+        // A node without variance is only shifted to its mean.
+        assert_eq!(outputs[0][1], 0.0);
+        assert!((batch_norm.running_mean[0] - 0.2).abs() < 1e-12 && (batch_norm.running_mean[1] - 1.0).abs() < 1e-12);
+
+        // Inference uses the running statistics, and a training batch of one data point does as well.
+        let inference = batch_norm.forward(vec![vec![2.0, 10.0]], false);
+        let running_variance = batch_norm.running_variance.clone();
+        assert_eq!(batch_norm.forward(vec![vec![2.0, 10.0]], true), inference);
+        assert_eq!(batch_norm.running_variance, running_variance);
+        assert!((inference[0][0] - 1.8 / (0.9 + 0.1 + EPSILON).sqrt()).abs() < 1e-9);
+
+        let mut layer_norm: LayerNorm<f64> = LayerNorm::new(2);
+        layer_norm.beta = vec![0.5, -0.5];
+        assert_eq!(layer_norm.forward(inputs.clone(), true), layer_norm.forward(inputs.clone(), false));
+
+        let layers: [Box<dyn Layer<f64>>; 2] = [Box::new(batch_norm), Box::new(layer_norm)];
+        for mut saved in layers {
+            let mut text = vec![];
+            saved.save(&mut text).unwrap();
+            let mut reader = RecordReader::new(&text[..]);
+            let mut loaded: Box<dyn Layer<f64>> = match saved.name() {
+                "batch_norm" => Box::new(BatchNorm::load(&mut reader).unwrap()),
+                _ => Box::new(LayerNorm::load(&mut reader).unwrap()),
+            };
+
+            assert_eq!(loaded.forward(inputs.clone(), false), saved.forward(inputs.clone(), false));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use nannou::image::codecs::gif::{GifEncoder, Repeat};
+use nannou::image::{Delay, Frame, RgbaImage};
+
+use crate::data_point::DataPoint;
+use crate::decision_boundary::{BoundaryImage, Region};
+use crate::headless;
+use crate::history::History;
+use crate::neural_network::NeuralNetwork;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFormat {
+    // Numbered PNG files in a directory.
+    PNG,
+    // One animated GIF file.
+    GIF,
+}
+
+impl RecordFormat {
+    // GIF for paths ending in .gif, a directory of PNG frames otherwise.
+    pub fn of_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => RecordFormat::GIF,
+            _ => RecordFormat::PNG,
+        }
+    }
+}
+
+// The path if nothing exists there yet, otherwise the first free one numbered before the extension, like
+// recording_2.gif for recording.gif.
+pub fn unused_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+
+    std::iter::once(path.to_path_buf())
+        .chain((2..).map(|number| path.with_file_name(format!("{}_{}{}", stem, number, extension))))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// Records the headless view of a network every number of training steps.
+pub struct Recorder {
+    pub format: RecordFormat,
+    // Training steps between two frames.
+    pub every: usize,
+    // Frames per second of the GIF.
+    pub frame_rate: u32,
+    pub width: usize,
+    pub height: usize,
+    // Number of frames recorded so far.
+    pub frames: usize,
+    // Directory of the PNG frames or the GIF file.
+    pub path: PathBuf,
+    last_step: Option<usize>,
+    gif: Option<GifEncoder<BufWriter<File>>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, format: RecordFormat, every: usize, frame_rate: u32, width: usize, height: usize) -> io::Result<Self> {
+        let gif = match format {
+            RecordFormat::PNG => {
+                fs::create_dir_all(path)?;
+                None
+            }
+            RecordFormat::GIF => {
+                let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+                encoder.set_repeat(Repeat::Infinite).map_err(io::Error::other)?;
+                Some(encoder)
+            }
+        };
+
+        Ok(Recorder {
+            format,
+            every: every.max(1),
+            frame_rate: frame_rate.max(1),
+            width,
+            height,
+            frames: 0,
+            path: path.to_path_buf(),
+            last_step: None,
+            gif,
+        })
+    }
+
+    // Whether a frame is due at a training step. The first one always is, and so is the first one after the training
+    // was restarted.
+    pub fn is_due(&self, step: usize) -> bool {
+        self.last_step.is_none_or(|last_step| step < last_step || step >= last_step + self.every)
+    }
+
+    // Render and record a frame if one is due. Returns whether it was.
+    pub fn capture(&mut self, network: &mut NeuralNetwork, data: &[DataPoint], history: Option<&History>, region: &Region, step: usize) -> io::Result<bool> {
+        if !self.is_due(step) {
+            return Ok(false);
+        }
+
+        let canvas = headless::render_view(network, data, history, region, self.width, self.height);
+        self.record(canvas.image())?;
+        self.last_step = Some(step);
+
+        Ok(true)
+    }
+
+    // Add a frame. A finished GIF takes no more frames.
+    pub fn record(&mut self, image: BoundaryImage) -> io::Result<()> {
+        match (self.format, &mut self.gif) {
+            (RecordFormat::PNG, _) => headless::save_png(image, &self.path.join(format!("frame_{:05}.png", self.frames)))?,
+            (RecordFormat::GIF, Some(encoder)) => {
+                let image = RgbaImage::from_raw(image.width as u32, image.height as u32, image.pixels).unwrap();
+                let delay = Delay::from_numer_denom_ms(1000, self.frame_rate);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay)).map_err(io::Error::other)?;
+            }
+            (RecordFormat::GIF, None) => return Err(io::Error::other(format!("{} is already finished", self.path.display()))),
+        }
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    // Finish the GIF. Returns the number of frames.
+    pub fn finish(&mut self) -> usize {
+        self.gif = None;
+
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationType;
+
+    #[test]
+    fn records_frames_every_steps() {
+        let mut network = NeuralNetwork::new(vec![2, 2], &ActivationType::SIGMOID);
+        let data = vec![DataPoint::new(vec![0.5, 0.5], 0, 2)];
+        let region = Region::new(0.0, 1.0, 0.0, 1.0);
+
+        let directory = std::env::temp_dir().join(format!("nn-rust-recorder-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let mut recorder = Recorder::create(&directory, RecordFormat::of_path(&directory), 3, 10, 16, 12).unwrap();
+        let captured: Vec<bool> = (1..=7)
+            .map(|step| recorder.capture(&mut network, &data, None, &region, step).unwrap())
+            .collect();
+        assert_eq!(captured, vec![true, false, false, true, false, false, true]);
+        assert!(recorder.is_due(1));
+        assert_eq!(recorder.finish(), 3);
+        assert!(directory.join("frame_00002.png").exists());
+
+        let path = std::env::temp_dir().join(format!("nn-rust-recorder-test-{}.gif", std::process::id()));
+        let mut recorder = Recorder::create(&path, RecordFormat::of_path(&path), 1, 10, 16, 12).unwrap();
+        assert_eq!(recorder.format, RecordFormat::GIF);
+        for step in 0..2 {
+            recorder.capture(&mut network, &data, None, &region, step).unwrap();
+        }
+        assert_eq!(recorder.finish(), 2);
+        assert!(fs::read(&path).unwrap().starts_with(b"GIF89a"));
+        assert!(recorder.capture(&mut network, &data, None, &region, 10).is_err());
+
+        assert_eq!(unused_path(&path), path.with_file_name(format!("nn-rust-recorder-test-{}_2.gif", std::process::id())));
+        assert_eq!(unused_path(&directory), directory.with_file_name(format!("nn-rust-recorder-test-{}_2", std::process::id())));
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(unused_path(&path), path);
+    }
+}
@@ -0,0 +1,578 @@
+use std::io::{self, BufRead, Write};
+
+use crate::float::Float;
+use crate::layer::{self, Layer, Parameters};
+use crate::regularization::Regularization;
+use crate::serialization::{self, RecordReader};
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CellType {
+    // Elman network: h = tanh(W x + U h + b).
+    RNN,
+    GRU,
+    LSTM,
+}
+
+// Weights of one gate: the inputs and the previous hidden state are mapped to the pre-activations of the gate.
+pub struct Gate<T: Float = f32> {
+    // [features][hidden]
+    pub input_weights: Vec<Vec<T>>,
+    // [hidden][hidden]
+    pub recurrent_weights: Vec<Vec<T>>,
+    pub biases: Vec<T>,
+    pub cost_gradient_input_weights: Vec<Vec<T>>,
+    pub cost_gradient_recurrent_weights: Vec<Vec<T>>,
+    pub cost_gradient_biases: Vec<T>,
+}
+
+// Values of a single time step, kept for the backward pass.
+struct Step<T: Float> {
+    input: Vec<T>,
+    hidden_previous: Vec<T>,
+    cell_previous: Vec<T>,
+    // Activations of the gates, in the order of `Recurrent::gates`.
+    gates: Vec<Vec<T>>,
+    // GRU only: reset gate applied to the previous hidden state.
+    reset_hidden: Vec<T>,
+    cell: Vec<T>,
+}
+
+// Recurrent layer over sequences of shape [time steps, features], trained with (truncated) backpropagation through time.
+pub struct Recurrent<T: Float = f32> {
+    pub cell_type: CellType,
+    pub time_steps: usize,
+    pub num_features: usize,
+    pub num_hidden: usize,
+    // Output the hidden state of every time step ([time steps, hidden]) instead of only the last one ([hidden]).
+    pub return_sequences: bool,
+    // Only propagate gradients back through this many time steps.
+    pub truncation: Option<usize>,
+    // Clip the gradients flowing back through time to [-clip, clip].
+    pub gradient_clip: Option<T>,
+    // RNN: [hidden], GRU: [update, reset, candidate], LSTM: [input, forget, candidate, output].
+    pub gates: Vec<Gate<T>>,
+    pub regularization: Regularization<T>,
+    // Learn data of the last batch.
+    steps: Vec<Vec<Step<T>>>,
+}
+
+// Applies a layer to every time step of a sequence of shape [time steps, features].
+pub struct TimeDistributed<T: Float = f32> {
+    pub time_steps: usize,
+    pub num_features: usize,
+    pub layer: Box<dyn Layer<T>>,
+}
+
+impl<T: Float> Gate<T> {
+    fn new(num_features: usize, num_hidden: usize, bias: T) -> Self {
+        // Scale the initial weights by the number of inputs of every node.
+        let boundary = T::one() / T::of((num_features + num_hidden) as f64).sqrt();
+        let random_weights = |rows: usize| -> Vec<Vec<T>> {
+            (0..rows)
+                .map(|_| (0..num_hidden).map(|_| -boundary + T::random() * T::of(2.0) * boundary).collect())
+                .collect()
+        };
+
+        Gate {
+            input_weights: random_weights(num_features),
+            recurrent_weights: random_weights(num_hidden),
+            biases: vec![bias; num_hidden],
+            cost_gradient_input_weights: vec![vec![T::zero(); num_hidden]; num_features],
+            cost_gradient_recurrent_weights: vec![vec![T::zero(); num_hidden]; num_hidden],
+            cost_gradient_biases: vec![T::zero(); num_hidden],
+        }
+    }
+
+    fn weighted_inputs(&self, input: &[T], hidden: &[T]) -> Vec<T> {
+        let mut weighted_inputs = self.biases.clone();
+
+        for (row, &value) in self.input_weights.iter().zip(input).chain(self.recurrent_weights.iter().zip(hidden)) {
+            for (weighted_input, &weight) in weighted_inputs.iter_mut().zip(row) {
+                *weighted_input += value * weight;
+            }
+        }
+
+        weighted_inputs
+    }
+
+    // Accumulate the cost gradients of the gate and add the gradients with respect to its inputs.
+    fn backward(&mut self, input: &[T], hidden: &[T], node_values: &[T], input_gradients: &mut [T], hidden_gradients: &mut [T]) {
+        for node in 0..node_values.len() {
+            self.cost_gradient_biases[node] += node_values[node];
+        }
+
+        let weights = self.input_weights.iter().zip(self.cost_gradient_input_weights.iter_mut()).zip(input).zip(input_gradients.iter_mut())
+            .chain(self.recurrent_weights.iter().zip(self.cost_gradient_recurrent_weights.iter_mut()).zip(hidden).zip(hidden_gradients.iter_mut()));
+
+        for (((row, gradient_row), &value), value_gradient) in weights {
+            for node in 0..node_values.len() {
+                gradient_row[node] += value * node_values[node];
+                *value_gradient += row[node] * node_values[node];
+            }
+        }
+    }
+
+    fn reset_gradients(&mut self) {
+        for row in self.cost_gradient_input_weights.iter_mut().chain(self.cost_gradient_recurrent_weights.iter_mut()) {
+            row.fill(T::zero());
+        }
+        self.cost_gradient_biases.fill(T::zero());
+    }
+
+    fn clip_gradients(&mut self, clip: T) {
+        for row in self.cost_gradient_input_weights.iter_mut().chain(self.cost_gradient_recurrent_weights.iter_mut()) {
+            clip_values(row, clip);
+        }
+        clip_values(&mut self.cost_gradient_biases, clip);
+    }
+}
+
+impl<T: Float> Recurrent<T> {
+    pub fn new(input_shape: &[usize], cell_type: &CellType, num_hidden: usize, return_sequences: bool) -> Self {
+        assert_eq!(input_shape.len(), 2, "expected a sequence shape [time steps, features], got {:?}", input_shape);
+        let (time_steps, num_features) = (input_shape[0], input_shape[1]);
+
+        let gates = match cell_type {
+            CellType::RNN => vec![Gate::new(num_features, num_hidden, T::zero())],
+            CellType::GRU => (0..3).map(|_| Gate::new(num_features, num_hidden, T::zero())).collect(),
+            // Start with an open forget gate, so the cell remembers by default.
+            CellType::LSTM => (0..4).map(|gate| Gate::new(num_features, num_hidden, if gate == 1 { T::one() } else { T::zero() })).collect(),
+        };
+
+        Recurrent {
+            cell_type: *cell_type,
+            time_steps,
+            num_features,
+            num_hidden,
+            return_sequences,
+            truncation: None,
+            gradient_clip: None,
+            gates,
+            regularization: Regularization::none(),
+            steps: vec![],
+        }
+    }
+
+    // Run a single time step, returning the new hidden state.
+    fn step(&self, input: &[T], hidden_previous: &[T], cell_previous: &[T]) -> (Vec<T>, Step<T>) {
+        let mut step = Step {
+            input: input.to_vec(),
+            hidden_previous: hidden_previous.to_vec(),
+            cell_previous: cell_previous.to_vec(),
+            gates: vec![],
+            reset_hidden: vec![],
+            cell: vec![],
+        };
+
+        let hidden = match self.cell_type {
+            CellType::RNN => {
+                let hidden: Vec<T> = self.gates[0].weighted_inputs(input, hidden_previous).into_iter().map(T::tanh).collect();
+                step.gates.push(hidden.clone());
+                hidden
+            }
+            CellType::GRU => {
+                let update: Vec<T> = self.gates[0].weighted_inputs(input, hidden_previous).into_iter().map(sigmoid).collect();
+                let reset: Vec<T> = self.gates[1].weighted_inputs(input, hidden_previous).into_iter().map(sigmoid).collect();
+                step.reset_hidden = reset.iter().zip(hidden_previous).map(|(&reset, &hidden)| reset * hidden).collect();
+                let candidate: Vec<T> = self.gates[2].weighted_inputs(input, &step.reset_hidden).into_iter().map(T::tanh).collect();
+
+                let hidden = (0..self.num_hidden)
+                    .map(|node| (T::one() - update[node]) * candidate[node] + update[node] * hidden_previous[node])
+                    .collect();
+                step.gates = vec![update, reset, candidate];
+                hidden
+            }
+            CellType::LSTM => {
+                let gates: Vec<Vec<T>> = self.gates.iter()
+                    .enumerate()
+                    .map(|(index, gate)| {
+                        let weighted_inputs = gate.weighted_inputs(input, hidden_previous).into_iter();
+                        if index == 2 { weighted_inputs.map(T::tanh).collect() } else { weighted_inputs.map(sigmoid).collect() }
+                    })
+                    .collect();
+                let (input_gate, forget, candidate, output) = (&gates[0], &gates[1], &gates[2], &gates[3]);
+
+                step.cell = (0..self.num_hidden)
+                    .map(|node| forget[node] * cell_previous[node] + input_gate[node] * candidate[node])
+                    .collect();
+                let hidden = (0..self.num_hidden).map(|node| output[node] * step.cell[node].tanh()).collect();
+                step.gates = gates;
+                hidden
+            }
+        };
+
+        (hidden, step)
+    }
+
+    // Backpropagate one time step. Returns the gradients with respect to the input and the previous hidden and cell state.
+    fn step_backward(&mut self, step: &Step<T>, hidden_gradients: &[T], cell_gradients: &[T]) -> (Vec<T>, Vec<T>, Vec<T>) {
+        let num_hidden = self.num_hidden;
+        let mut input_gradients = vec![T::zero(); self.num_features];
+        let mut hidden_previous_gradients = vec![T::zero(); num_hidden];
+        let mut cell_previous_gradients = vec![T::zero(); num_hidden];
+
+        match self.cell_type {
+            CellType::RNN => {
+                let hidden = &step.gates[0];
+                let node_values: Vec<T> = (0..num_hidden).map(|node| hidden_gradients[node] * (T::one() - hidden[node] * hidden[node])).collect();
+                self.gates[0].backward(&step.input, &step.hidden_previous, &node_values, &mut input_gradients, &mut hidden_previous_gradients);
+            }
+            CellType::GRU => {
+                let (update, reset, candidate) = (&step.gates[0], &step.gates[1], &step.gates[2]);
+
+                let candidate_values: Vec<T> = (0..num_hidden)
+                    .map(|node| hidden_gradients[node] * (T::one() - update[node]) * (T::one() - candidate[node] * candidate[node]))
+                    .collect();
+                let mut reset_hidden_gradients = vec![T::zero(); num_hidden];
+                self.gates[2].backward(&step.input, &step.reset_hidden, &candidate_values, &mut input_gradients, &mut reset_hidden_gradients);
+
+                let update_values: Vec<T> = (0..num_hidden)
+                    .map(|node| hidden_gradients[node] * (step.hidden_previous[node] - candidate[node]) * update[node] * (T::one() - update[node]))
+                    .collect();
+                let reset_values: Vec<T> = (0..num_hidden)
+                    .map(|node| reset_hidden_gradients[node] * step.hidden_previous[node] * reset[node] * (T::one() - reset[node]))
+                    .collect();
+                self.gates[0].backward(&step.input, &step.hidden_previous, &update_values, &mut input_gradients, &mut hidden_previous_gradients);
+                self.gates[1].backward(&step.input, &step.hidden_previous, &reset_values, &mut input_gradients, &mut hidden_previous_gradients);
+
+                for node in 0..num_hidden {
+                    hidden_previous_gradients[node] += hidden_gradients[node] * update[node] + reset_hidden_gradients[node] * reset[node];
+                }
+            }
+            CellType::LSTM => {
+                let (input_gate, forget, candidate, output) = (&step.gates[0], &step.gates[1], &step.gates[2], &step.gates[3]);
+
+                let mut gate_values = vec![vec![T::zero(); num_hidden]; 4];
+                for node in 0..num_hidden {
+                    let cell_tanh = step.cell[node].tanh();
+                    let cell_gradient = cell_gradients[node] + hidden_gradients[node] * output[node] * (T::one() - cell_tanh * cell_tanh);
+
+                    gate_values[0][node] = cell_gradient * candidate[node] * input_gate[node] * (T::one() - input_gate[node]);
+                    gate_values[1][node] = cell_gradient * step.cell_previous[node] * forget[node] * (T::one() - forget[node]);
+                    gate_values[2][node] = cell_gradient * input_gate[node] * (T::one() - candidate[node] * candidate[node]);
+                    gate_values[3][node] = hidden_gradients[node] * cell_tanh * output[node] * (T::one() - output[node]);
+                    cell_previous_gradients[node] = cell_gradient * forget[node];
+                }
+
+                for (gate, node_values) in self.gates.iter_mut().zip(&gate_values) {
+                    gate.backward(&step.input, &step.hidden_previous, node_values, &mut input_gradients, &mut hidden_previous_gradients);
+                }
+            }
+        }
+
+        (input_gradients, hidden_previous_gradients, cell_previous_gradients)
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let record = reader.record("recurrent")?;
+        let value = |index: usize| serialization::parse::<usize>(record.get(index), "recurrent");
+        let cell_type = match record.first().map(String::as_str) {
+            Some("rnn") => CellType::RNN,
+            Some("gru") => CellType::GRU,
+            Some("lstm") => CellType::LSTM,
+            other => return Err(serialization::invalid_data(format!("unknown cell type '{}'", other.unwrap_or_default()))),
+        };
+
+        let mut recurrent = Recurrent::new(&[value(1)?, value(2)?], &cell_type, value(3)?, value(4)? == 1);
+        let truncation = value(5)?;
+        recurrent.truncation = if truncation > 0 { Some(truncation) } else { None };
+        recurrent.gradient_clip = reader.values("gradient_clip")?.first().copied();
+
+        let num_hidden = recurrent.num_hidden;
+        for gate in &mut recurrent.gates {
+            for row in gate.input_weights.iter_mut() {
+                *row = layer::read_exact(reader, "input_weights", num_hidden)?;
+            }
+            for row in gate.recurrent_weights.iter_mut() {
+                *row = layer::read_exact(reader, "recurrent_weights", num_hidden)?;
+            }
+            gate.biases = layer::read_exact(reader, "biases", num_hidden)?;
+        }
+
+        Ok(recurrent)
+    }
+}
+
+impl<T: Float> Layer<T> for Recurrent<T> {
+    fn name(&self) -> &'static str {
+        "recurrent"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        if self.return_sequences {
+            vec![self.time_steps, self.num_hidden]
+        } else {
+            vec![self.num_hidden]
+        }
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, _training: bool) -> Vec<Vec<T>> {
+        self.steps = Vec::with_capacity(inputs.len());
+
+        inputs.iter()
+            .map(|input| {
+                let mut hidden = vec![T::zero(); self.num_hidden];
+                let mut cell = vec![T::zero(); self.num_hidden];
+                let mut outputs = Vec::with_capacity(self.time_steps * self.num_hidden);
+                let mut steps = Vec::with_capacity(self.time_steps);
+
+                for features in input.chunks(self.num_features) {
+                    let (next_hidden, step) = self.step(features, &hidden, &cell);
+                    hidden = next_hidden;
+                    if self.cell_type == CellType::LSTM {
+                        cell = step.cell.clone();
+                    }
+                    if self.return_sequences {
+                        outputs.extend_from_slice(&hidden);
+                    }
+                    steps.push(step);
+                }
+
+                self.steps.push(steps);
+                if self.return_sequences { outputs } else { hidden }
+            })
+            .collect()
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        for gate in &mut self.gates {
+            gate.reset_gradients();
+        }
+
+        let steps = std::mem::take(&mut self.steps);
+        let mut input_gradients = Vec::with_capacity(output_gradients.len());
+
+        for (gradients, sequence) in output_gradients.iter().zip(&steps) {
+            let mut sequence_gradients = vec![T::zero(); self.time_steps * self.num_features];
+            let mut hidden_gradients = vec![T::zero(); self.num_hidden];
+            let mut cell_gradients = vec![T::zero(); self.num_hidden];
+
+            for time in (0..sequence.len()).rev() {
+                // Cut the gradients flowing back through time at the truncation boundaries.
+                if let Some(truncation) = self.truncation {
+                    if (time + 1) % truncation == 0 {
+                        hidden_gradients.fill(T::zero());
+                        cell_gradients.fill(T::zero());
+                    }
+                }
+
+                if self.return_sequences {
+                    for node in 0..self.num_hidden {
+                        hidden_gradients[node] += gradients[time * self.num_hidden + node];
+                    }
+                } else if time == sequence.len() - 1 {
+                    hidden_gradients = gradients.clone();
+                }
+
+                if let Some(clip) = self.gradient_clip {
+                    clip_values(&mut hidden_gradients, clip);
+                    clip_values(&mut cell_gradients, clip);
+                }
+
+                let (step_input_gradients, hidden_previous_gradients, cell_previous_gradients) = self.step_backward(&sequence[time], &hidden_gradients, &cell_gradients);
+                sequence_gradients[time * self.num_features..(time + 1) * self.num_features].copy_from_slice(&step_input_gradients);
+                hidden_gradients = hidden_previous_gradients;
+                cell_gradients = cell_previous_gradients;
+            }
+
+            input_gradients.push(sequence_gradients);
+        }
+
+        self.steps = steps;
+
+        if let Some(clip) = self.gradient_clip {
+            for gate in &mut self.gates {
+                gate.clip_gradients(clip);
+            }
+        }
+
+        input_gradients
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        let regularization = &self.regularization;
+        let mut parameters = vec![];
+
+        for gate in &mut self.gates {
+            let rows = gate.input_weights.iter_mut().zip(gate.cost_gradient_input_weights.iter_mut())
+                .chain(gate.recurrent_weights.iter_mut().zip(gate.cost_gradient_recurrent_weights.iter_mut()));
+            for (values, gradients) in rows {
+                parameters.push(Parameters { values, gradients, regularization: Some(regularization) });
+            }
+
+            parameters.push(Parameters {
+                values: &mut gate.biases,
+                gradients: &mut gate.cost_gradient_biases,
+                regularization: if regularization.include_biases { Some(regularization) } else { None },
+            });
+        }
+
+        parameters
+    }
+
+    fn set_regularization(&mut self, regularization: &Regularization<T>) {
+        self.regularization = regularization.clone();
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let cell_type = match self.cell_type {
+            CellType::RNN => "rnn",
+            CellType::GRU => "gru",
+            CellType::LSTM => "lstm",
+        };
+        serialization::write_record(writer, "recurrent", &[
+            cell_type.to_string(),
+            self.time_steps.to_string(),
+            self.num_features.to_string(),
+            self.num_hidden.to_string(),
+            (self.return_sequences as usize).to_string(),
+            self.truncation.unwrap_or(0).to_string(),
+        ])?;
+        // Empty when the gradients are not clipped.
+        serialization::write_values(writer, "gradient_clip", &self.gradient_clip.iter().copied().collect::<Vec<T>>())?;
+
+        for gate in &self.gates {
+            for row in &gate.input_weights {
+                serialization::write_values(writer, "input_weights", row)?;
+            }
+            for row in &gate.recurrent_weights {
+                serialization::write_values(writer, "recurrent_weights", row)?;
+            }
+            serialization::write_values(writer, "biases", &gate.biases)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Float> TimeDistributed<T> {
+    pub fn new(input_shape: &[usize], layer: Box<dyn Layer<T>>) -> Self {
+        assert_eq!(input_shape.len(), 2, "expected a sequence shape [time steps, features], got {:?}", input_shape);
+
+        TimeDistributed {
+            time_steps: input_shape[0],
+            num_features: input_shape[1],
+            layer,
+        }
+    }
+
+    // Turn a batch of sequences into a batch of time steps and back.
+    fn split(values: Vec<Vec<T>>, time_steps: usize) -> Vec<Vec<T>> {
+        values.iter()
+            .flat_map(|sequence| sequence.chunks(sequence.len() / time_steps).map(|step| step.to_vec()).collect::<Vec<Vec<T>>>())
+            .collect()
+    }
+
+    fn join(values: Vec<Vec<T>>, time_steps: usize) -> Vec<Vec<T>> {
+        values.chunks(time_steps)
+            .map(|steps| steps.concat())
+            .collect()
+    }
+
+    pub fn load<R: BufRead>(reader: &mut RecordReader<R>) -> io::Result<Self> {
+        let record = reader.record("time_distributed")?;
+        let time_steps = serialization::parse(record.first(), "time_distributed")?;
+        let num_features = serialization::parse(record.get(1), "time_distributed")?;
+
+        Ok(TimeDistributed::new(&[time_steps, num_features], layer::load(reader)?))
+    }
+}
+
+impl<T: Float> Layer<T> for TimeDistributed<T> {
+    fn name(&self) -> &'static str {
+        "time_distributed"
+    }
+
+    fn output_shape(&self, _input_shape: &[usize]) -> Vec<usize> {
+        let mut shape = vec![self.time_steps];
+        shape.extend(self.layer.output_shape(&[self.num_features]));
+
+        shape
+    }
+
+    fn forward(&mut self, inputs: Vec<Vec<T>>, training: bool) -> Vec<Vec<T>> {
+        let outputs = self.layer.forward(Self::split(inputs, self.time_steps), training);
+
+        Self::join(outputs, self.time_steps)
+    }
+
+    fn backward(&mut self, output_gradients: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        let input_gradients = self.layer.backward(Self::split(output_gradients, self.time_steps));
+
+        Self::join(input_gradients, self.time_steps)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameters<'_, T>> {
+        self.layer.parameters()
+    }
+
+    fn set_regularization(&mut self, regularization: &Regularization<T>) {
+        self.layer.set_regularization(regularization);
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serialization::write_record(writer, "time_distributed", &[self.time_steps.to_string(), self.num_features.to_string()])?;
+
+        layer::save(self.layer.as_ref(), writer)
+    }
+}
+
+fn sigmoid<T: Float>(value: T) -> T {
+    T::one() / (T::one() + (-value).exp())
+}
+
+fn clip_values<T: Float>(values: &mut [T], clip: T) {
+    for value in values.iter_mut() {
+        *value = value.max(-clip).min(clip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationType;
+    use crate::data_point::DataPoint;
+    use crate::dense::Dense;
+    use crate::neural_network::NeuralNetwork;
+
+    const BITS: usize = 8;
+
+    // Two numbers as a sequence of bit pairs, least significant bit first, and their sum as the expected output bits.
+    fn binary_addition(a: usize, b: usize) -> DataPoint<f64> {
+        let bit = |number: usize, index: usize| ((number >> index) & 1) as f64;
+        let sum = a + b;
+
+        DataPoint::sequence(
+            (0..BITS).map(|index| vec![bit(a, index), bit(b, index)]).collect(),
+            (0..BITS).map(|index| vec![bit(sum, index)]).collect(),
+            sum,
+        )
+    }
+
+    #[test]
+    fn learns_binary_addition() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![BITS, 2]);
+        network.add(Box::new(Recurrent::new(&network.output_shape(), &CellType::RNN, 16, true)));
+        let dense = Dense::new(16, 1, &ActivationType::SIGMOID);
+        network.add(Box::new(TimeDistributed::new(&network.output_shape(), Box::new(dense))));
+
+        let mut random = snippets::seeded_random_numbers(7).map(|random| random as usize % 128);
+        for _ in 0..800 {
+            let batch: Vec<DataPoint<f64>> = (0..16)
+                .map(|_| binary_addition(random.next().unwrap(), random.next().unwrap()))
+                .collect();
+            network.learn(&batch, 0.5).unwrap();
+        }
+
+        for (a, b) in [(3, 5), (100, 27), (64, 64), (127, 1), (77, 50)] {
+            let data_point = binary_addition(a, b);
+            let outputs = network.calculate_outputs(data_point.inputs);
+            let sum: usize = outputs.iter()
+                .enumerate()
+                .map(|(index, &output)| if output > 0.5 { 1 << index } else { 0 })
+                .sum();
+            assert_eq!(sum, a + b);
+        }
+    }
+}
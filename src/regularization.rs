@@ -0,0 +1,58 @@
+use crate::float::Float;
+
+// Penalties on the size of the parameters of a layer to combat overfitting.
+#[derive(Clone)]
+pub struct Regularization<T: Float = f32> {
+    // Strength of the L1 penalty: l1 * |w|.
+    pub l1: T,
+    // Strength of the L2 penalty: l2 * w^2.
+    pub l2: T,
+    // Shrink factor applied directly to the parameters on every update, independent of the gradients.
+    pub weight_decay: T,
+    // Whether the biases are regularized as well as the weights.
+    pub include_biases: bool,
+}
+
+impl<T: Float> Regularization<T> {
+    pub fn new(l1: T, l2: T, weight_decay: T, include_biases: bool) -> Self {
+        Regularization {
+            l1,
+            l2,
+            weight_decay,
+            include_biases,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self::new(T::zero(), T::zero(), T::zero(), false)
+    }
+
+    // Penalty added to the cost for a single parameter.
+    pub fn penalty(&self, value: T) -> T {
+        self.l1 * value.abs() + self.l2 * value * value
+    }
+
+    // Derivative of the penalty with respect to a single parameter.
+    pub fn penalty_derivative(&self, value: T) -> T {
+        let sign = if value > T::zero() {
+            T::one()
+        } else if value < T::zero() {
+            -T::one()
+        } else {
+            T::zero()
+        };
+
+        self.l1 * sign + T::of(2.0) * self.l2 * value
+    }
+
+    // Decoupled weight decay: shrink the parameter towards zero, scaled by the learn rate.
+    pub fn decay(&self, value: T, learn_rate: T) -> T {
+        value - value * self.weight_decay * learn_rate
+    }
+}
+
+impl<T: Float> Default for Regularization<T> {
+    fn default() -> Self {
+        Self::none()
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::{self, BufRead, Lines, Write};
+
+use crate::float::Float;
+
+// Plain text format: one record per line, a key followed by whitespace separated values.
+pub fn write_record<W: Write + ?Sized>(writer: &mut W, key: &str, values: &[String]) -> io::Result<()> {
+    writeln!(writer, "{} {}", key, values.join(" "))
+}
+
+pub fn write_values<W: Write + ?Sized, T: Float>(writer: &mut W, key: &str, values: &[T]) -> io::Result<()> {
+    let values: Vec<String> = values.iter().map(|value| format!("{:?}", value)).collect();
+
+    write_record(writer, key, &values)
+}
+
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub struct RecordReader<R: BufRead> {
+    lines: Lines<R>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R) -> Self {
+        RecordReader {
+            lines: reader.lines(),
+        }
+    }
+
+    // Read the next record, which has to have the given key.
+    pub fn record(&mut self, key: &str) -> io::Result<Vec<String>> {
+        let line = self.lines.next()
+            .ok_or_else(|| invalid_data(format!("expected '{}', found end of file", key)))??;
+        let mut tokens = line.split_whitespace().map(str::to_string);
+
+        match tokens.next() {
+            Some(found) if found == key => Ok(tokens.collect()),
+            found => Err(invalid_data(format!("expected '{}', found '{}'", key, found.unwrap_or_default()))),
+        }
+    }
+
+    pub fn values<T: Float>(&mut self, key: &str) -> io::Result<Vec<T>> {
+        self.record(key)?
+            .iter()
+            .map(|token| T::from_str_radix(token, 10)
+                .map_err(|_| invalid_data(format!("invalid number '{}' in '{}'", token, key))))
+            .collect()
+    }
+}
+
+pub fn parse<V: std::str::FromStr>(token: Option<&String>, key: &str) -> io::Result<V> {
+    token.and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid_data(format!("invalid or missing value in '{}'", key)))
+}
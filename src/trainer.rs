@@ -0,0 +1,165 @@
+use crate::callback::{self, Callback, Control, TrainingState};
+use crate::data_point::DataPoint;
+use crate::float::Float;
+use crate::neural_network::{LearnError, NeuralNetwork};
+
+// Trains a network without a window: mini-batch gradient descent over a number of epochs.
+pub struct Trainer<T: Float = f32> {
+    pub learn_rate: T,
+    pub batch_size: usize,
+    pub max_epochs: usize,
+}
+
+impl<T: Float> Trainer<T> {
+    pub fn new(learn_rate: T, batch_size: usize, max_epochs: usize) -> Self {
+        assert!(batch_size > 0, "the batch size of a trainer must be at least 1");
+
+        Trainer {
+            learn_rate,
+            batch_size,
+            max_epochs,
+        }
+    }
+
+    // Train until the maximum number of epochs or until a callback stops it. Returns the number of epochs run.
+    // The validation metrics are only calculated if there is validation data. Data the network can not take is
+    // reported before training.
+    pub fn train(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        training_data: &Vec<DataPoint<T>>,
+        validation_data: &Vec<DataPoint<T>>,
+        callbacks: &mut [&mut dyn Callback<T>],
+    ) -> Result<usize, LearnError> {
+        network.check_inputs(training_data)
+            .and_then(|()| network.check_inputs(validation_data))
+            .map_err(LearnError::InvalidInput)?;
+
+        let mut state = TrainingState::default();
+        let mut control = callback::notify(callbacks, |callback| callback.on_train_start(network, &state));
+
+        while control == Control::Continue && state.epoch < self.max_epochs {
+            state.epoch += 1;
+            state.batch = 0;
+            state.batch_cost = None;
+            state.gradient_norm = None;
+            state.training_cost = None;
+            state.training_accuracy = None;
+            state.validation_cost = None;
+            state.validation_accuracy = None;
+
+            if callback::notify(callbacks, |callback| callback.on_epoch_start(network, &state)) == Control::Stop {
+                break;
+            }
+
+            let mut total_cost = T::zero();
+            for batch in training_data.chunks(self.batch_size) {
+                state.batch += 1;
+                state.step += 1;
+
+                match network.learn(&batch.to_vec(), self.learn_rate) {
+                    Ok(cost) => {
+                        total_cost += cost;
+                        state.learn_rate = Some(self.learn_rate);
+                        state.batch_cost = Some(cost);
+                        state.gradient_norm = Some(network.gradient_norm());
+                    }
+                    Err(error) => {
+                        if let LearnError::Diverged(divergence) = &error {
+                            for callback in callbacks.iter_mut() {
+                                callback.on_divergence(network, &state, divergence);
+                            }
+                        }
+                        for callback in callbacks.iter_mut() {
+                            callback.on_train_end(network, &state);
+                        }
+
+                        return Err(error);
+                    }
+                }
+
+                control = callback::notify(callbacks, |callback| callback.on_batch_end(network, &state));
+                if control == Control::Stop {
+                    break;
+                }
+            }
+
+            state.training_cost = Some(total_cost / T::of(state.batch as f64));
+            state.training_accuracy = Some(network.accuracy(training_data));
+            if !validation_data.is_empty() {
+                state.validation_cost = Some(network.cost(validation_data));
+                state.validation_accuracy = Some(network.accuracy(validation_data));
+            }
+
+            if callback::notify(callbacks, |callback| callback.on_epoch_end(network, &state)) == Control::Stop {
+                control = Control::Stop;
+            }
+        }
+
+        for callback in callbacks.iter_mut() {
+            callback.on_train_end(network, &state);
+        }
+
+        Ok(state.epoch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationType;
+    use crate::dense::Dense;
+    use crate::early_stopping::{EarlyStopping, Metric};
+    use crate::normalization::BatchNorm;
+
+    // Training data of two classes, and validation data with the opposite labels, so the validation cost stops improving.
+    fn conflicting_data() -> (Vec<DataPoint<f64>>, Vec<DataPoint<f64>>) {
+        let training_data: Vec<DataPoint<f64>> = (0..40)
+            .map(|_| {
+                let inputs = vec![f64::random(), f64::random()];
+                let label = if inputs[0] > inputs[1] { 1 } else { 0 };
+                DataPoint::new(inputs, label, 2)
+            })
+            .collect();
+        let validation_data = training_data.iter()
+            .map(|data_point| DataPoint::new(data_point.inputs.clone(), 1 - data_point.label, 2))
+            .collect();
+
+        (training_data, validation_data)
+    }
+
+    #[test]
+    fn stops_early_and_restores_best_weights() {
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::new(vec![2, 4, 2], &ActivationType::SIGMOID);
+        let (training_data, validation_data) = conflicting_data();
+
+        let mut early_stopping = EarlyStopping::new(Metric::COST, 5, 0.0);
+        let epochs = Trainer::new(1.0, 8, 1000).train(&mut network, &training_data, &validation_data, &mut [&mut early_stopping]).unwrap();
+
+        assert!(epochs < 1000);
+        assert_eq!(epochs, early_stopping.best_epoch + early_stopping.patience);
+        assert_eq!(Some(network.cost(&validation_data)), early_stopping.best);
+    }
+
+    #[test]
+    fn restores_batch_norm_statistics_of_the_best_epoch() {
+        // The validation cost is calculated with the running statistics, so it only matches the best cost if they are
+        // restored with the weights.
+        let mut network: NeuralNetwork<f64> = NeuralNetwork::sequential(vec![2]);
+        network.add(Box::new(Dense::new(2, 4, &ActivationType::SIGMOID)));
+        network.add(Box::new(BatchNorm::new(4)));
+        network.add(Box::new(Dense::new(4, 2, &ActivationType::SIGMOID)));
+        let (training_data, validation_data) = conflicting_data();
+
+        let mut early_stopping = EarlyStopping::new(Metric::COST, 5, 0.0);
+        Trainer::new(0.5, 8, 1000).train(&mut network, &training_data, &validation_data, &mut [&mut early_stopping]).unwrap();
+
+        assert_eq!(Some(network.cost(&validation_data)), early_stopping.best);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch size")]
+    fn rejects_an_empty_batch_size() {
+        Trainer::<f64>::new(0.5, 0, 10);
+    }
+}
@@ -0,0 +1,242 @@
+use nannou::prelude::*;
+
+const ROW_HEIGHT: f32 = 20.0;
+const PADDING: f32 = 6.0;
+const FONT_SIZE: u32 = 12;
+
+const COLOR_PANEL: (f32, f32, f32, f32) = (0.0, 0.0, 0.0, 0.7);
+const COLOR_WIDGET: (f32, f32, f32, f32) = (0.25, 0.27, 0.3, 1.0);
+const COLOR_HOVERED: (f32, f32, f32, f32) = (0.35, 0.38, 0.42, 1.0);
+const COLOR_FILL: (f32, f32, f32, f32) = (0.27, 0.51, 0.71, 1.0);
+
+// Maps a value between a minimum and a maximum to the unit interval, or back.
+type UnitMapping = fn(f32, f32, f32) -> f32;
+
+enum Shape {
+    Rect(Rect, (f32, f32, f32, f32)),
+    Text(String, Rect),
+}
+
+// Immediate mode widgets. They are declared every frame in `update`, react to the mouse right away and record what to
+// draw, which `view` draws afterwards.
+pub struct Ui {
+    mouse: Vec2,
+    down: bool,
+    // The mouse button went down this frame.
+    pressed: bool,
+    // Slider dragged with the mouse and the open dropdown, by id. The id of a dropdown is its label.
+    active: Option<String>,
+    open: Option<String>,
+    // List of the open dropdown, on top of the widgets below it.
+    list_rect: Option<Rect>,
+    rect: Rect,
+    // Top of the next row.
+    cursor: f32,
+    shapes: Vec<Shape>,
+    // Drawn on top of the shapes.
+    overlay: Vec<Shape>,
+}
+
+impl Ui {
+    pub fn new() -> Self {
+        Ui {
+            mouse: Vec2::ZERO,
+            down: false,
+            pressed: false,
+            active: None,
+            open: None,
+            list_rect: None,
+            rect: Rect::from_w_h(0.0, 0.0),
+            cursor: 0.0,
+            shapes: vec![],
+            overlay: vec![],
+        }
+    }
+
+    // Start a panel with its top left corner at a position, with the current state of the mouse.
+    pub fn begin(&mut self, top_left: Vec2, width: f32, mouse: Vec2, down: bool) {
+        self.pressed = down && !self.down;
+        self.mouse = mouse;
+        self.down = down;
+        if !down {
+            self.active = None;
+        }
+
+        self.list_rect = None;
+        self.rect = Rect::from_corners(top_left, top_left + vec2(width, 0.0));
+        self.cursor = top_left.y - PADDING;
+        self.shapes.clear();
+        self.overlay.clear();
+    }
+
+    // Close the panel. A dropdown that was not declared in this frame does not stay open.
+    pub fn end(&mut self) {
+        self.rect = Rect::from_corners(self.rect.top_left(), vec2(self.rect.right(), self.cursor - PADDING / 2.0));
+
+        if self.list_rect.is_none() {
+            self.open = None;
+        }
+    }
+
+    // Whether the panel covers a window position, so the mouse is not used for anything else there.
+    pub fn contains(&self, position: Vec2) -> bool {
+        self.rect.contains(position) || self.list_rect.is_some_and(|rect| rect.contains(position))
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let rect = self.row();
+        self.shapes.push(Shape::Text(text.to_string(), rect));
+    }
+
+    // Drag to set a value between a minimum and a maximum, spaced logarithmically if asked. Returns whether the value
+    // changed. The id identifies the slider while it is dragged, the label can change with the value.
+    pub fn slider(&mut self, id: &str, label: &str, value: &mut f32, min: f32, max: f32, log: bool) -> bool {
+        let rect = self.row();
+        if self.clicked(&rect) {
+            self.active = Some(id.to_string());
+        }
+
+        let (to_unit, from_unit): (UnitMapping, UnitMapping) = if log {
+            (|value, min, max| (value.ln() - min.ln()) / (max.ln() - min.ln()), |unit, min, max| (min.ln() + unit * (max.ln() - min.ln())).exp())
+        } else {
+            (|value, min, max| (value - min) / (max - min), |unit, min, max| min + unit * (max - min))
+        };
+
+        let mut changed = false;
+        if self.active.as_deref() == Some(id) {
+            let new_value = from_unit(((self.mouse.x - rect.left()) / rect.w()).clamp(0.0, 1.0), min, max);
+            changed = new_value != *value;
+            *value = new_value;
+        }
+
+        let unit = to_unit(value.clamp(min, max), min, max);
+        let fill = Rect::from_corners(rect.bottom_left(), rect.bottom_left() + vec2(rect.w() * unit, rect.h()));
+        self.shapes.push(Shape::Rect(rect, self.background(&rect)));
+        self.shapes.push(Shape::Rect(fill, COLOR_FILL));
+        self.shapes.push(Shape::Text(label.to_string(), rect.pad_left(PADDING)));
+
+        changed
+    }
+
+    pub fn button(&mut self, label: &str) -> bool {
+        self.buttons(&[label]) == Some(0)
+    }
+
+    // A row of buttons of equal width. Returns the index of the clicked one.
+    pub fn buttons(&mut self, labels: &[&str]) -> Option<usize> {
+        let row = self.row();
+        let width = (row.w() - PADDING * (labels.len() - 1) as f32) / labels.len() as f32;
+
+        let mut clicked = None;
+        for (index, label) in labels.iter().enumerate() {
+            let left = row.left() + index as f32 * (width + PADDING);
+            let rect = Rect::from_corners(vec2(left, row.bottom()), vec2(left + width, row.top()));
+            if self.clicked(&rect) {
+                clicked = Some(index);
+            }
+
+            self.shapes.push(Shape::Rect(rect, self.background(&rect)));
+            self.shapes.push(Shape::Text(label.to_string(), rect.pad_left(PADDING)));
+        }
+
+        clicked
+    }
+
+    // Pick one of the options from a list that opens on click. Returns whether the selection changed.
+    pub fn dropdown(&mut self, label: &str, options: &[&str], selected: &mut usize) -> bool {
+        let rect = self.row();
+        let is_open = self.open.as_deref() == Some(label);
+        if self.clicked(&rect) {
+            self.open = if is_open { None } else { Some(label.to_string()) };
+            self.pressed = false;
+        }
+
+        self.shapes.push(Shape::Rect(rect, self.background(&rect)));
+        self.shapes.push(Shape::Text(format!("{}: {} v", label, options[*selected]), rect.pad_left(PADDING)));
+
+        if self.open.as_deref() != Some(label) {
+            return false;
+        }
+
+        // The options are listed below, on top of the following widgets, which only get the clicks the list does not take.
+        let list_top = rect.bottom();
+        let option_rect = |index: usize| {
+            let top = list_top - index as f32 * ROW_HEIGHT;
+            Rect::from_corners(vec2(rect.left(), top - ROW_HEIGHT), vec2(rect.right(), top))
+        };
+
+        let mut changed = false;
+        if self.pressed {
+            if let Some(index) = (0..options.len()).find(|&index| option_rect(index).contains(self.mouse)) {
+                changed = index != *selected;
+                *selected = index;
+                self.pressed = false;
+            }
+            // Any click closes the list.
+            self.open = None;
+            return changed;
+        }
+
+        for (index, option) in options.iter().enumerate() {
+            let rect = option_rect(index);
+            let color = if index == *selected { COLOR_FILL } else { self.background(&rect) };
+            self.overlay.push(Shape::Rect(rect, color));
+            self.overlay.push(Shape::Text(option.to_string(), rect.pad_left(PADDING)));
+        }
+        self.list_rect = Some(Rect::from_corners(
+            vec2(rect.left(), list_top - options.len() as f32 * ROW_HEIGHT),
+            vec2(rect.right(), list_top),
+        ));
+
+        changed
+    }
+
+    pub fn draw(&self, draw: &Draw, z: f32) {
+        draw.rect()
+            .xy(self.rect.xy())
+            .wh(self.rect.wh())
+            .z(z)
+            .rgba(COLOR_PANEL.0, COLOR_PANEL.1, COLOR_PANEL.2, COLOR_PANEL.3);
+
+        for shape in self.shapes.iter().chain(&self.overlay) {
+            match shape {
+                Shape::Rect(rect, (r, g, b, a)) => {
+                    draw.rect()
+                        .xy(rect.xy())
+                        .wh(rect.wh())
+                        .z(z)
+                        .rgba(*r, *g, *b, *a);
+                }
+                Shape::Text(text, rect) => {
+                    draw.text(text)
+                        .xy(rect.xy())
+                        .wh(rect.wh())
+                        .z(z)
+                        .font_size(FONT_SIZE)
+                        .left_justify()
+                        .align_text_middle_y()
+                        .color(WHITE);
+                }
+            }
+        }
+    }
+
+    fn row(&mut self) -> Rect {
+        let rect = Rect::from_corners(
+            vec2(self.rect.left() + PADDING, self.cursor - ROW_HEIGHT),
+            vec2(self.rect.right() - PADDING, self.cursor),
+        );
+        self.cursor -= ROW_HEIGHT + PADDING / 2.0;
+
+        rect
+    }
+
+    // A click that was not taken by a widget before, e.g. by the list of an open dropdown above.
+    fn clicked(&self, rect: &Rect) -> bool {
+        self.pressed && rect.contains(self.mouse)
+    }
+
+    fn background(&self, rect: &Rect) -> (f32, f32, f32, f32) {
+        if rect.contains(self.mouse) { COLOR_HOVERED } else { COLOR_WIDGET }
+    }
+}